@@ -0,0 +1,30 @@
+//! Count the nodes of a given type in a binary XML file.
+//!
+//! Usage: `cargo run --example count_types -- <file> <type>`, e.g. `s32` or `str`.
+
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use kbinxml::StandardType;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let (path, type_name) = match (args.next(), args.next()) {
+        (Some(path), Some(type_name)) => (path, type_name),
+        _ => return Err("usage: count_types <file> <type>".into()),
+    };
+
+    let node_type = StandardType::from_name(&type_name)?;
+    let input = fs::read(path)?;
+    let (collection, _encoding) = kbinxml::from_slice(&input)?;
+
+    let count = std::iter::once(&collection)
+        .chain(collection.iter_descendants())
+        .filter(|node| node.base().node_type == node_type)
+        .count();
+
+    println!("{} {} node(s)", count, node_type);
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Print the path of the first node with a given key in a binary XML file.
+//!
+//! Usage: `cargo run --example find_first -- <file> <key>`
+
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use kbinxml::WalkControl;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let (path, key) = match (args.next(), args.next()) {
+        (Some(path), Some(key)) => (path, key),
+        _ => return Err("usage: find_first <file> <key>".into()),
+    };
+
+    let input = fs::read(path)?;
+    let (collection, _encoding) = kbinxml::from_slice(&input)?;
+    let root = collection.as_node()?;
+
+    // The keys of the current node's ancestors, indexed by depth
+    let mut path = Vec::new();
+    let mut found = false;
+
+    root.walk(|node, depth| {
+        path.truncate(depth);
+        path.push(node.key().to_owned());
+
+        if node.key() == key {
+            found = true;
+            WalkControl::Stop
+        } else {
+            WalkControl::Continue
+        }
+    });
+
+    if found {
+        println!("/{}", path.join("/"));
+    } else {
+        println!("no node named {:?}", key);
+    }
+
+    Ok(())
+}
@@ -70,47 +70,50 @@ pub enum ByteBufferError {
     SeekForward { size: usize, source: io::Error },
 }
 
+/// The data buffer alignment used by standard kbin files
+pub(crate) const DEFAULT_ALIGNMENT: u64 = 4;
+
 /// Remove trailing null bytes, used for the `String` type
 pub(crate) fn strip_trailing_null_bytes(data: &[u8]) -> &[u8] {
-    let len = data.len();
-
-    if len == 0 {
-        return data;
-    }
-
-    let mut index = len - 1;
-    while index > 0 && index < len && data[index] == 0x00 {
-        index -= 1;
-    }
+    let len = data
+        .iter()
+        .rposition(|&b| b != 0x00)
+        .map_or(0, |index| index + 1);
 
-    // Handle case where the buffer is only a null byte
-    if index == 0 && data.len() == 1 && data[index] == 0x00 {
-        &[]
-    } else {
-        &data[..=index]
-    }
+    &data[..len]
 }
 
+#[derive(Clone)]
 pub struct ByteBufferRead {
     cursor: Cursor<Bytes>,
     buffer: Bytes,
     offset_1: usize,
     offset_2: usize,
+    alignment: u64,
 }
 
 pub struct ByteBufferWrite {
     buffer: Cursor<Vec<u8>>,
     offset_1: u64,
     offset_2: u64,
+    used_1: bool,
+    used_2: bool,
+    alignment: u64,
 }
 
 impl ByteBufferRead {
     pub fn new(buffer: Bytes) -> Self {
+        Self::with_alignment(buffer, DEFAULT_ALIGNMENT)
+    }
+
+    /// Create a buffer that realigns reads to `alignment` bytes instead of 4.
+    pub fn with_alignment(buffer: Bytes, alignment: u64) -> Self {
         Self {
             cursor: Cursor::new(buffer.clone()),
             buffer,
             offset_1: 0,
             offset_2: 0,
+            alignment,
         }
     }
 
@@ -156,7 +159,21 @@ impl ByteBufferRead {
         Ok(data)
     }
 
+    /// Move to `offset`, resetting the slots used to pack 1 and 2 byte values so
+    /// that the next `get_aligned` read of such a value starts at `offset`.
+    pub fn seek_to(&mut self, offset: usize) -> Result<(), ByteBufferError> {
+        self.check_read_size(offset, 0)?;
+        self.cursor.set_position(offset as u64);
+        self.offset_1 = offset;
+        self.offset_2 = offset;
+
+        Ok(())
+    }
+
     pub fn buf_read(&mut self) -> Result<Bytes, ByteBufferError> {
+        // Report a missing length the same way as missing data, which also covers
+        // an empty data buffer
+        self.check_read_size(self.data_buf_offset(), 4)?;
         let size = self.cursor.read_u32::<BigEndian>().context(ReadSizeSnafu)?;
         debug!(
             "buf_read => index: {}, size: {}",
@@ -178,10 +195,10 @@ impl ByteBufferRead {
     }
 
     pub fn get_aligned(&mut self, node_type: StandardType) -> Result<Bytes, ByteBufferError> {
-        if self.offset_1 % 4 == 0 {
+        if self.offset_1.is_multiple_of(4) {
             self.offset_1 = self.data_buf_offset();
         }
-        if self.offset_2 % 4 == 0 {
+        if self.offset_2.is_multiple_of(4) {
             self.offset_2 = self.data_buf_offset();
         }
 
@@ -206,7 +223,7 @@ impl ByteBufferRead {
             },
             size => {
                 let data = self
-                    .buf_read_size(size as usize)
+                    .buf_read_size(size)
                     .map_err(Box::new)
                     .context(ReadAlignedSnafu { size })?;
                 self.realign_reads(None)?;
@@ -235,14 +252,14 @@ impl ByteBufferRead {
     }
 
     pub fn realign_reads(&mut self, size: Option<u64>) -> Result<(), ByteBufferError> {
-        let size = size.unwrap_or(4);
+        let size = size.unwrap_or(self.alignment);
         trace!(
             "realign_reads => position: {}, size: {}",
             self.cursor.position(),
             size
         );
 
-        while self.cursor.position() % size > 0 {
+        while !self.cursor.position().is_multiple_of(size) {
             self.cursor
                 .seek(SeekFrom::Current(1))
                 .context(SeekForwardSnafu { size: 1usize })?;
@@ -251,14 +268,28 @@ impl ByteBufferRead {
 
         Ok(())
     }
+
+    /// Consume the buffer, returning the underlying bytes.
+    #[cfg_attr(not(feature = "internals"), allow(dead_code))]
+    pub fn into_inner(self) -> Bytes {
+        self.buffer
+    }
 }
 
 impl ByteBufferWrite {
     pub fn new(buffer: Vec<u8>) -> Self {
+        Self::with_alignment(buffer, DEFAULT_ALIGNMENT)
+    }
+
+    /// Create a buffer that realigns writes to `alignment` bytes instead of 4.
+    pub fn with_alignment(buffer: Vec<u8>, alignment: u64) -> Self {
         Self {
             buffer: Cursor::new(buffer),
             offset_1: 0,
             offset_2: 0,
+            used_1: false,
+            used_2: false,
+            alignment,
         }
     }
 
@@ -317,10 +348,10 @@ impl ByteBufferWrite {
         node_type: StandardType,
         data: &[u8],
     ) -> Result<(), ByteBufferError> {
-        if self.offset_1 % 4 == 0 {
+        if self.offset_1.is_multiple_of(4) {
             self.offset_1 = self.data_buf_offset();
         }
-        if self.offset_2 % 4 == 0 {
+        if self.offset_2.is_multiple_of(4) {
             self.offset_2 = self.data_buf_offset();
         }
 
@@ -344,7 +375,7 @@ impl ByteBufferWrite {
         let check_old = match size {
             1 => {
                 // Make room for new DWORD
-                if self.offset_1 % 4 == 0 {
+                if self.offset_1.is_multiple_of(4) {
                     self.buffer
                         .write_u32::<BigEndian>(0)
                         .context(WritePaddingSnafu { size: 4usize })?;
@@ -359,12 +390,13 @@ impl ByteBufferWrite {
                     .write_u8(data[0])
                     .context(WriteDataByteSnafu { offset: 1usize })?;
                 self.offset_1 += 1;
+                self.used_1 = true;
 
                 true
             },
             2 => {
                 // Make room for new DWORD
-                if self.offset_2 % 4 == 0 {
+                if self.offset_2.is_multiple_of(4) {
                     self.buffer
                         .write_u32::<BigEndian>(0)
                         .context(WritePaddingSnafu { size: 4usize })?;
@@ -382,6 +414,7 @@ impl ByteBufferWrite {
                     .write_u8(data[1])
                     .context(WriteDataByteSnafu { offset: 2usize })?;
                 self.offset_2 += 2;
+                self.used_2 = true;
 
                 true
             },
@@ -419,15 +452,69 @@ impl ByteBufferWrite {
         Ok(())
     }
 
+    /// Check if `other`, encoded on its own from an empty buffer, can be appended
+    /// to this buffer without changing the output compared to writing its contents
+    /// directly into this buffer.
+    ///
+    /// This is not the case when this buffer has a partially filled 1 or 2 byte
+    /// slot and `other` wrote values of the same size, as those values would have
+    /// been packed into the open slot instead of a new one.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn can_append(&self, other: &ByteBufferWrite) -> bool {
+        let open_1 = !self.offset_1.is_multiple_of(4);
+        let open_2 = !self.offset_2.is_multiple_of(4);
+
+        !(open_1 && other.used_1 || open_2 && other.used_2)
+    }
+
+    /// Append the contents of `other` to this buffer, rebasing the alignment state
+    /// of `other` onto the current position. Callers must check `can_append` first.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn append(&mut self, other: ByteBufferWrite) -> Result<(), ByteBufferError> {
+        let base = self.data_buf_offset();
+        debug_assert!(base.is_multiple_of(4));
+        debug_assert!(self.can_append(&other));
+
+        if other.used_1 {
+            self.offset_1 = base + other.offset_1;
+            self.used_1 = true;
+        }
+        if other.used_2 {
+            self.offset_2 = base + other.offset_2;
+            self.used_2 = true;
+        }
+
+        let position = other.buffer.position();
+        self.buffer
+            .write_all(other.buffer.get_ref())
+            .context(WriteDataBlockSnafu)?;
+        self.buffer
+            .seek(SeekFrom::Start(base + position))
+            .context(SeekOffsetSnafu {
+                offset: (base + position) as usize,
+            })?;
+
+        Ok(())
+    }
+
+    /// Append the contents of `other` to this buffer as-is, for buffers that do
+    /// not pack values into shared slots, such as the node buffer.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn append_raw(&mut self, other: ByteBufferWrite) -> Result<(), ByteBufferError> {
+        self.buffer
+            .write_all(other.buffer.get_ref())
+            .context(WriteDataBlockSnafu)
+    }
+
     pub fn realign_writes(&mut self, size: Option<u64>) -> Result<(), ByteBufferError> {
-        let size = size.unwrap_or(4);
+        let size = size.unwrap_or(self.alignment);
         trace!(
             "realign_writes => position: {}, size: {}",
             self.buffer.position(),
             size
         );
 
-        while self.buffer.position() % size > 0 {
+        while !self.buffer.position().is_multiple_of(size) {
             self.buffer
                 .write_u8(0)
                 .context(WritePaddingSnafu { size: 1usize })?;
@@ -437,6 +524,13 @@ impl ByteBufferWrite {
 
         Ok(())
     }
+
+    /// Write zero bytes until the position is a multiple of `alignment`,
+    /// regardless of the alignment the buffer was created with. An alignment of
+    /// 0 or 1 writes nothing.
+    pub fn pad_to(&mut self, alignment: u64) -> Result<(), ByteBufferError> {
+        self.realign_writes(Some(alignment.max(1)))
+    }
 }
 
 impl Deref for ByteBufferRead {
@@ -466,3 +560,56 @@ impl DerefMut for ByteBufferWrite {
         &mut self.buffer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_null_bytes() {
+        assert_eq!(strip_trailing_null_bytes(b""), b"");
+        assert_eq!(strip_trailing_null_bytes(b"\0"), b"");
+        assert_eq!(strip_trailing_null_bytes(b"\0\0"), b"");
+        assert_eq!(strip_trailing_null_bytes(b"a\0b\0\0"), b"a\0b");
+        assert_eq!(strip_trailing_null_bytes(b"\0a"), b"\0a");
+    }
+
+    #[test]
+    fn empty_read_buffer() {
+        let mut buf = ByteBufferRead::new(Bytes::new());
+
+        assert!(matches!(
+            buf.buf_read(),
+            Err(ByteBufferError::OutOfBounds { offset: 0, size: 4 })
+        ));
+        assert!(matches!(
+            buf.get_aligned(StandardType::U8),
+            Err(ByteBufferError::OutOfBounds { offset: 0, size: 1 })
+        ));
+        assert!(matches!(
+            buf.get_aligned(StandardType::S16),
+            Err(ByteBufferError::OutOfBounds { offset: 0, size: 2 })
+        ));
+        assert!(matches!(
+            buf.get_aligned(StandardType::U32),
+            Err(ByteBufferError::ReadAligned { size: 4, .. })
+        ));
+        assert!(buf.get(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pad_to() {
+        let mut buf = ByteBufferWrite::new(Vec::new());
+        buf.buf_write(&[1, 2, 3, 4, 5]).unwrap();
+        buf.pad_to(0).unwrap();
+        assert_eq!(buf.position(), 12);
+        buf.pad_to(16).unwrap();
+        assert_eq!(buf.position(), 16);
+
+        let mut buf = ByteBufferRead::new(Bytes::from(buf.into_inner()));
+        assert_eq!(&buf.buf_read().unwrap()[..], &[1, 2, 3, 4, 5]);
+        buf.realign_reads(Some(16)).unwrap();
+        assert_eq!(buf.position(), 16);
+        assert_eq!(buf.into_inner().len(), 16);
+    }
+}
@@ -1,10 +1,11 @@
 use std::error::Error;
 use std::fmt;
 
-use crate::{SIG_COMPRESSED, SIG_UNCOMPRESSED};
+use crate::{SIGNATURE, SIG_COMPRESSED, SIG_UNCOMPRESSED};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum CompressionType {
+    #[default]
     Compressed,
     Uncompressed,
 }
@@ -21,6 +22,16 @@ impl CompressionType {
         }
     }
 
+    /// Read the compression type from the header of a binary XML document
+    /// without parsing the rest of it. Returns `None` if the input does not
+    /// start with the binary XML signature and a known compression byte.
+    pub fn detect(input: &[u8]) -> Option<Self> {
+        match input {
+            [SIGNATURE, byte, ..] => Self::from_byte(*byte).ok(),
+            _ => None,
+        }
+    }
+
     pub fn to_byte(&self) -> u8 {
         match *self {
             CompressionType::Compressed => SIG_COMPRESSED,
@@ -29,12 +40,6 @@ impl CompressionType {
     }
 }
 
-impl Default for CompressionType {
-    fn default() -> Self {
-        CompressionType::Compressed
-    }
-}
-
 impl fmt::Display for UnknownCompression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Unknown compression type: 0x{:x}", self.0)
@@ -42,3 +47,23 @@ impl fmt::Display for UnknownCompression {
 }
 
 impl Error for UnknownCompression {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            CompressionType::detect(&[SIGNATURE, SIG_COMPRESSED, 0x80, 0x7F]),
+            Some(CompressionType::Compressed)
+        );
+        assert_eq!(
+            CompressionType::detect(&[SIGNATURE, SIG_UNCOMPRESSED]),
+            Some(CompressionType::Uncompressed)
+        );
+        assert_eq!(CompressionType::detect(&[SIGNATURE, 0x00]), None);
+        assert_eq!(CompressionType::detect(&[SIGNATURE]), None);
+        assert_eq!(CompressionType::detect(b"<?xml"), None);
+    }
+}
@@ -13,6 +13,9 @@ pub enum EncodingError {
     #[snafu(display("Unknown encoding"))]
     UnknownEncoding,
 
+    #[snafu(display("Unknown encoding name: {}", name))]
+    UnknownEncodingName { name: String },
+
     #[snafu(display("Another encoding was used to decode the input: {:?}", actual))]
     MismatchedDecode { actual: &'static Encoding },
 
@@ -30,25 +33,29 @@ pub enum EncodingError {
 
     #[snafu(display("Failed to convert string to alternate encoding"))]
     Convert,
+
+    #[snafu(display(
+        "Failed to decode input with any of the attempted encodings: {}",
+        attempted.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    ))]
+    NoMatchingDecode {
+        attempted: Vec<EncodingType>,
+        source: Box<EncodingError>,
+    },
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum EncodingType {
     None,
     ASCII,
     ISO_8859_1,
     EUC_JP,
+    #[default]
     SHIFT_JIS,
     UTF_8,
 }
 
-impl Default for EncodingType {
-    fn default() -> Self {
-        EncodingType::SHIFT_JIS
-    }
-}
-
 impl fmt::Display for EncodingType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let encoding = match *self {
@@ -65,6 +72,18 @@ impl fmt::Display for EncodingType {
 }
 
 impl EncodingType {
+    /// Every supported encoding, in order of their header byte.
+    pub fn all() -> &'static [EncodingType] {
+        &[
+            EncodingType::None,
+            EncodingType::ASCII,
+            EncodingType::ISO_8859_1,
+            EncodingType::EUC_JP,
+            EncodingType::SHIFT_JIS,
+            EncodingType::UTF_8,
+        ]
+    }
+
     pub fn from_byte(byte: u8) -> Result<Self, EncodingError> {
         let val = match byte {
             0x00 => EncodingType::None,
@@ -95,6 +114,27 @@ impl EncodingType {
             .and_then(Self::from_encoding)
     }
 
+    /// Look up an encoding by name, such as from a command line argument.
+    ///
+    /// The names written by `Display` are accepted ignoring case and with `_`
+    /// in place of `-`, as are the labels accepted by `from_label`, such as
+    /// `sjis` or `latin1`.
+    pub fn from_name(name: &str) -> Result<Self, EncodingError> {
+        let normalized = name.replace('_', "-");
+        let found = Self::all()
+            .iter()
+            .find(|encoding| encoding.to_string().eq_ignore_ascii_case(&normalized));
+
+        match found {
+            Some(encoding) => Ok(*encoding),
+            None => {
+                Self::from_label(name.as_bytes()).map_err(|_| EncodingError::UnknownEncodingName {
+                    name: name.to_owned(),
+                })
+            },
+        }
+    }
+
     pub fn to_byte(&self) -> u8 {
         match *self {
             EncodingType::None => 0x00,       // 0x00 >> 5 = 0
@@ -171,6 +211,19 @@ impl EncodingType {
         }
     }
 
+    /// Returns `true` if decoding `input` replaces invalid characters. Only
+    /// `SHIFT_JIS` decodes such input, the other encodings return an error.
+    pub(crate) fn replaces_characters(&self, input: &[u8]) -> bool {
+        let encoding = match *self {
+            EncodingType::ISO_8859_1 => WINDOWS_1252,
+            EncodingType::EUC_JP => EUC_JP,
+            EncodingType::SHIFT_JIS => SHIFT_JIS,
+            EncodingType::None | EncodingType::ASCII | EncodingType::UTF_8 => return false,
+        };
+
+        encoding.decode(input).2
+    }
+
     /// Decode bytes using the encoding definition from the `encoding` crate.
     ///
     /// A `Some` value indicates an encoding should be used from the `encoding`
@@ -191,24 +244,218 @@ impl EncodingType {
         }
     }
 
-    /// Encode bytes using the encoding definition from the `encoding` crate.
+    /// Decode bytes like `decode_bytes`, trying each encoding in `fallbacks` in
+    /// order if decoding with this encoding fails. Some files declare the wrong
+    /// encoding, so this recovers their strings.
     ///
-    /// A `Some` value indicates the encoding should be used from the `encoding`
-    /// crate. A `None` value indicates Rust's own UTF-8 handling should be used.
-    pub fn encode_bytes(&self, input: &str) -> Result<Vec<u8>, EncodingError> {
-        let mut result = match *self {
-            EncodingType::None | EncodingType::UTF_8 => input.as_bytes().to_vec(),
-
-            EncodingType::ASCII => Self::encode_ascii(input)?,
-            EncodingType::ISO_8859_1 => Self::encode_with_encoding(WINDOWS_1252, input)?,
-            EncodingType::EUC_JP => Self::encode_with_encoding(EUC_JP, input)?,
-            EncodingType::SHIFT_JIS => Self::encode_with_encoding(SHIFT_JIS, input)?,
+    /// If every encoding fails, the error lists all attempted encodings and
+    /// holds the error from the last one.
+    pub fn decode_bytes_fallback(
+        &self,
+        input: &[u8],
+        fallbacks: &[EncodingType],
+    ) -> Result<String, EncodingError> {
+        let mut attempted = vec![*self];
+        let mut last_error = match self.decode_bytes(input) {
+            Ok(output) => return Ok(output),
+            Err(e) => e,
         };
 
+        for encoding in fallbacks {
+            match encoding.decode_bytes(input) {
+                Ok(output) => {
+                    warn!(
+                        "Failed to decode input as {}, decoded as {} instead",
+                        self, encoding
+                    );
+                    return Ok(output);
+                },
+                Err(e) => {
+                    attempted.push(*encoding);
+                    last_error = e;
+                },
+            }
+        }
+
+        Err(EncodingError::NoMatchingDecode {
+            attempted,
+            source: Box::new(last_error),
+        })
+    }
+
+    /// Encode a string to bytes with a trailing null byte, as kbin stores strings.
+    ///
+    /// This is `encode_bytes_raw` followed by the terminator, so the result must
+    /// not be terminated again.
+    pub fn encode_bytes(&self, input: &str) -> Result<Vec<u8>, EncodingError> {
+        let mut result = self.encode_bytes_raw(input)?;
+
         // Add trailing null byte
         result.reserve_exact(1);
         result.push(0);
 
         Ok(result)
     }
+
+    /// Encode a string to bytes using the encoding definition from the `encoding`
+    /// crate, without the trailing null byte added by `encode_bytes`.
+    ///
+    /// `EncodingType::None` and `EncodingType::UTF_8` use Rust's own UTF-8
+    /// handling.
+    pub fn encode_bytes_raw(&self, input: &str) -> Result<Vec<u8>, EncodingError> {
+        // Every supported encoding is a superset of ASCII, so ASCII input, such as
+        // most keys, is copied as-is. The extra byte of capacity is for the null
+        // byte added by `encode_bytes`.
+        if input.is_ascii() {
+            let mut result = Vec::with_capacity(input.len() + 1);
+            result.extend_from_slice(input.as_bytes());
+
+            return Ok(result);
+        }
+
+        match *self {
+            EncodingType::None | EncodingType::UTF_8 => Ok(input.as_bytes().to_vec()),
+
+            EncodingType::ASCII => Self::encode_ascii(input),
+            EncodingType::ISO_8859_1 => Self::encode_with_encoding(WINDOWS_1252, input),
+            EncodingType::EUC_JP => Self::encode_with_encoding(EUC_JP, input),
+            EncodingType::SHIFT_JIS => Self::encode_with_encoding(SHIFT_JIS, input),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use super::*;
+
+    use test::{black_box, Bencher};
+
+    use crate::compression_type::CompressionType;
+    use crate::node::Node;
+    use crate::options::Options;
+    use crate::value::Value;
+
+    const ASCII: &str = "hello, world";
+    const JAPANESE: &str = "こんにちは、世界";
+    const LATIN_1: &str = "café déjà vu";
+
+    fn samples(encoding: EncodingType) -> &'static [&'static str] {
+        match encoding {
+            EncodingType::ASCII => &[ASCII],
+            EncodingType::ISO_8859_1 => &[ASCII, LATIN_1],
+            EncodingType::EUC_JP | EncodingType::SHIFT_JIS => &[ASCII, JAPANESE],
+            EncodingType::None | EncodingType::UTF_8 => &[ASCII, JAPANESE, LATIN_1],
+        }
+    }
+
+    #[test]
+    fn decode_fallback() {
+        // `encode_bytes` appends a null terminator
+        let mut shift_jis = EncodingType::SHIFT_JIS.encode_bytes(JAPANESE).unwrap();
+        shift_jis.pop();
+        assert!(EncodingType::UTF_8.decode_bytes(&shift_jis).is_err());
+
+        let fallbacks = [EncodingType::ASCII, EncodingType::SHIFT_JIS];
+        let output = EncodingType::UTF_8
+            .decode_bytes_fallback(&shift_jis, &fallbacks)
+            .unwrap();
+        assert_eq!(output, JAPANESE);
+
+        let output = EncodingType::UTF_8
+            .decode_bytes_fallback(JAPANESE.as_bytes(), &fallbacks)
+            .unwrap();
+        assert_eq!(output, JAPANESE);
+
+        match EncodingType::UTF_8
+            .decode_bytes_fallback(b"\xff\xfe", &[EncodingType::ASCII, EncodingType::EUC_JP])
+        {
+            Err(EncodingError::NoMatchingDecode { attempted, .. }) => assert_eq!(attempted, [
+                EncodingType::UTF_8,
+                EncodingType::ASCII,
+                EncodingType::EUC_JP
+            ]),
+            result => panic!("expected decode failure, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn all_round_trip() {
+        for &encoding in EncodingType::all() {
+            assert_eq!(
+                EncodingType::from_byte(encoding.to_byte()).unwrap(),
+                encoding
+            );
+
+            for &input in samples(encoding) {
+                // `encode_bytes` appends a trailing null byte
+                let bytes = encoding.encode_bytes(input).unwrap();
+                assert_eq!(bytes.last(), Some(&0));
+                let decoded = encoding.decode_bytes(&bytes[..bytes.len() - 1]).unwrap();
+                assert_eq!(decoded, input, "{}", encoding);
+
+                let node =
+                    Node::with_attrs_value("s", &[("a", input)], Value::String(input.into()));
+                let options = Options::builder().encoding(encoding).build();
+                let binary = node.to_binary_with_options(options).unwrap();
+                let (collection, read_encoding) = crate::from_slice(&binary).unwrap();
+
+                assert_eq!(read_encoding, encoding);
+                assert_eq!(collection.as_node().unwrap(), node, "{}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn ascii_fast_path() {
+        // Every byte that is not a control character
+        let input: String = (0x20u8..0x7f).map(char::from).collect();
+
+        for encoding in EncodingType::all() {
+            let expected = match *encoding {
+                EncodingType::None | EncodingType::UTF_8 | EncodingType::ASCII => {
+                    input.as_bytes().to_vec()
+                },
+                EncodingType::ISO_8859_1 => {
+                    EncodingType::encode_with_encoding(WINDOWS_1252, &input).unwrap()
+                },
+                EncodingType::EUC_JP => EncodingType::encode_with_encoding(EUC_JP, &input).unwrap(),
+                EncodingType::SHIFT_JIS => {
+                    EncodingType::encode_with_encoding(SHIFT_JIS, &input).unwrap()
+                },
+            };
+
+            let mut output = encoding.encode_bytes(&input).unwrap();
+            assert_eq!(output.pop(), Some(0));
+            assert_eq!(output, expected, "{}", encoding);
+            assert_eq!(encoding.encode_bytes_raw(&input).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn encode_bytes_raw() {
+        let encoding = EncodingType::SHIFT_JIS;
+        let raw = encoding.encode_bytes_raw("テスト").unwrap();
+        assert_eq!(raw, b"\x83\x65\x83\x58\x83\x67");
+
+        let mut terminated = encoding.encode_bytes("テスト").unwrap();
+        assert_eq!(terminated.pop(), Some(0));
+        assert_eq!(terminated, raw);
+
+        assert!(EncodingType::UTF_8.encode_bytes_raw("").unwrap().is_empty());
+    }
+
+    #[bench]
+    fn bench_write_uncompressed_keys(b: &mut Bencher) {
+        let mut root = Node::new("root");
+        for i in 0..256 {
+            let mut entry = Node::with_attrs(format!("entry_{}", i), &[("id", "1"), ("kind", "a")]);
+            entry.append_child(Node::with_value("name", Value::String("value".into())));
+            root.append_child(entry);
+        }
+        let options = Options::new(CompressionType::Uncompressed, EncodingType::SHIFT_JIS);
+
+        b.iter(|| black_box(root.to_binary_with_options(options.clone()).unwrap()));
+    }
 }
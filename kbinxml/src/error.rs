@@ -27,6 +27,18 @@ pub enum KbinError {
     #[snafu(display("No node collection found"))]
     NoNodeCollection,
 
+    #[snafu(display(
+        "Truncated document at offset {} (expected length: {}, remaining: {})",
+        offset,
+        expected,
+        remaining
+    ))]
+    TruncatedDocument {
+        offset: usize,
+        expected: usize,
+        remaining: usize,
+    },
+
     #[snafu(display(
         "Size Mismatch, type: {}, expected size: {}, actual size: {}",
         node_type,
@@ -57,6 +69,9 @@ pub enum KbinError {
         source: ParseFloatError,
     },
 
+    #[snafu(display("Unexpected whitespace in input for {}", node_type))]
+    UnexpectedWhitespace { node_type: &'static str },
+
     #[snafu(display("Unable to convert from hexadecimal"))]
     Hex { source: FromHexError },
 
@@ -72,15 +87,42 @@ pub enum KbinError {
         value: Value,
     },
 
+    #[snafu(display("Value {:?} is out of range for {}", value, node_type))]
+    ValueOutOfRange {
+        node_type: StandardType,
+        value: Value,
+    },
+
     #[snafu(display("Value mismatch, expected an array, but found {:?}", value))]
     ExpectedValueArray { value: Value },
 
+    #[snafu(display("Node type {} can not be used as an array", node_type))]
+    NonArrayableType { node_type: StandardType },
+
     #[snafu(display("Invalid input for boolean: {}", input))]
     InvalidBooleanInput { input: u8 },
 
     #[snafu(display("Invalid node type for operation: {:?}", node_type))]
     InvalidNodeType { node_type: StandardType },
 
+    #[snafu(display("Time is before the Unix epoch"))]
+    TimeBeforeEpoch,
+
+    #[snafu(display(
+        "Time of {} seconds since the Unix epoch does not fit in a u32",
+        seconds
+    ))]
+    TimeOutOfRange { seconds: u64 },
+
+    #[snafu(display("Invalid radix: {}, must be between 2 and 36", radix))]
+    InvalidRadix { radix: u32 },
+
+    #[snafu(display(
+        "Unknown compression name: {}, expected compressed or uncompressed",
+        name
+    ))]
+    UnknownCompressionName { name: String },
+
     #[snafu(display("Invalid state"))]
     InvalidState,
 
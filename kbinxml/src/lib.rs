@@ -5,6 +5,9 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 
+use std::iter;
+
+use byteorder::{BigEndian, ByteOrder};
 use bytes::Bytes;
 
 mod byte_buffer;
@@ -14,6 +17,8 @@ mod error;
 mod node;
 mod node_types;
 mod options;
+#[cfg(feature = "rayon")]
+mod parallel;
 mod printer;
 mod reader;
 mod sixbit;
@@ -28,17 +33,23 @@ use crate::text_reader::TextXmlReader;
 use crate::to_text_xml::TextXmlWriter;
 
 // Public exports
+#[cfg(feature = "internals")]
+pub use crate::byte_buffer::{ByteBufferError, ByteBufferRead, ByteBufferWrite};
 pub use crate::compression_type::CompressionType;
 pub use crate::encoding_type::EncodingType;
 pub use crate::error::KbinError;
-pub use crate::node::{Node, NodeCollection};
+pub use crate::node::{
+    Key, Node, NodeCollection, NodeData, NodeDefinition, Schema, SchemaEntry, WalkControl,
+};
 pub use crate::node_types::StandardType;
-pub use crate::options::{Options, OptionsBuilder};
+pub use crate::options::{BufferLayout, Options, OptionsBuilder};
+#[cfg(feature = "rayon")]
+pub use crate::parallel::ParallelWriteable;
 pub use crate::printer::Printer;
-pub use crate::reader::Reader;
+pub use crate::reader::{FileStats, ParseWarning, Reader};
 pub use crate::to_text_xml::ToTextXml;
-pub use crate::value::{Value, ValueArray};
-pub use crate::writer::{Writeable, Writer};
+pub use crate::value::{ArrayElement, Value, ValueArray};
+pub use crate::writer::{NodeObserver, Writeable, Writer};
 
 const SIGNATURE: u8 = 0xA0;
 
@@ -47,12 +58,25 @@ const SIG_UNCOMPRESSED: u8 = 0x45;
 
 const ARRAY_MASK: u8 = 1 << 6; // 1 << 6 = 64
 
+/// The input format detected by `from_bytes_detect`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    Binary,
+    TextXml,
+}
+
 pub fn is_binary_xml(input: &[u8]) -> bool {
     input.len() > 2 &&
         input[0] == SIGNATURE &&
         (input[1] == SIG_COMPRESSED || input[1] == SIG_UNCOMPRESSED)
 }
 
+/// Read a binary XML document.
+///
+/// The returned `NodeCollection` does not copy the input. Its keys and values
+/// are `Bytes` slices of `input`, which keep the underlying buffer alive until
+/// the last of them is dropped. Use `NodeCollection::as_node` for a `Node` tree
+/// that owns its data.
 pub fn from_binary(input: Bytes) -> Result<(NodeCollection, EncodingType)> {
     let reader = Reader::new(input)?;
     let encoding = reader.encoding();
@@ -63,6 +87,126 @@ pub fn from_binary(input: Bytes) -> Result<(NodeCollection, EncodingType)> {
     Ok((collection, encoding))
 }
 
+/// Read a binary XML document like `from_binary`, also returning the
+/// recoverable problems found in it instead of only logging them.
+///
+/// String and attribute values are decoded to check for replaced characters, so
+/// this is slower than `from_binary`.
+pub fn from_binary_with_report(
+    input: Bytes,
+) -> Result<(NodeCollection, EncodingType, Vec<ParseWarning>)> {
+    let reader = Reader::new(input)?;
+    let encoding = reader.encoding();
+
+    let mut warnings = Vec::new();
+    let trailing = reader.trailing_bytes().len();
+    if trailing > 0 {
+        warnings.push(ParseWarning::TrailingData { bytes: trailing });
+    }
+
+    let collection = reader
+        .collect::<Option<NodeCollection>>()
+        .ok_or(KbinError::NoNodeCollection)?;
+
+    let mut stack = vec![&collection];
+    while let Some(collection) = stack.pop() {
+        for definition in iter::once(collection.base()).chain(collection.attributes()) {
+            let replaced = match definition.value_bytes() {
+                Some(data) if definition.node_type.is_textual() => {
+                    encoding.replaces_characters(data)
+                },
+                _ => false,
+            };
+
+            if replaced {
+                let key = definition.key()?.unwrap_or_default();
+                warnings.push(ParseWarning::CharacterReplaced { key });
+            }
+        }
+
+        // Reversed so that warnings are in document order
+        stack.extend(collection.children().iter().rev());
+    }
+
+    Ok((collection, encoding, warnings))
+}
+
+/// Read the node definitions of a binary XML document in order, without
+/// assembling them into a `NodeCollection`.
+///
+/// Unlike iterating over a `Reader`, read errors are yielded instead of ending
+/// the iteration silently. The iterator ends after the `FileEnd` definition or
+/// the first error.
+pub fn node_definitions(input: Bytes) -> Result<impl Iterator<Item = Result<NodeDefinition>>> {
+    let mut reader = Reader::new(input)?;
+    let mut done = false;
+
+    Ok(iter::from_fn(move || {
+        if done {
+            return None;
+        }
+
+        let definition = reader.read_node_definition().map_err(KbinError::from);
+        done = match definition {
+            Ok(ref definition) => definition.node_type == StandardType::FileEnd,
+            Err(_) => true,
+        };
+
+        Some(definition)
+    }))
+}
+
+/// Read a binary XML document without taking ownership of `input`.
+///
+/// This is the same as `from_binary`: cloning `Bytes` only increments a
+/// reference count, and the returned `NodeCollection` shares the buffer of
+/// `input` rather than copying it.
+pub fn from_binary_borrowed(input: &Bytes) -> Result<(NodeCollection, EncodingType)> {
+    from_binary(input.clone())
+}
+
+/// Get the total length of the binary XML document at the start of `input` from
+/// the node and data buffer lengths in its header. If `input` is too short to
+/// read a length, the number of bytes needed to read it is returned instead.
+fn binary_document_len(input: &[u8]) -> usize {
+    // Same offsets as `Reader::new`: the 8 byte header ending with the node buffer
+    // length, the node buffer, then the data buffer length and the data buffer
+    let len_node = match input.get(4..8) {
+        Some(bytes) => BigEndian::read_u32(bytes) as usize,
+        None => return 8,
+    };
+    let len_data_offset = 8 + len_node;
+    let len_data = match input.get(len_data_offset..len_data_offset + 4) {
+        Some(bytes) => BigEndian::read_u32(bytes) as usize,
+        None => return len_data_offset + 4,
+    };
+
+    len_data_offset + 4 + len_data
+}
+
+/// Read multiple binary XML documents stored back-to-back in `input`.
+pub fn from_binary_multi(input: Bytes) -> Result<Vec<(NodeCollection, EncodingType)>> {
+    let mut documents = Vec::new();
+    let mut offset = 0;
+
+    while offset < input.len() {
+        let remaining = input.len() - offset;
+        let len = binary_document_len(&input[offset..]);
+        if len > remaining {
+            return Err(KbinError::TruncatedDocument {
+                offset,
+                expected: len,
+                remaining,
+            });
+        }
+
+        documents.push(from_binary(input.slice(offset..offset + len))?);
+        offset += len;
+    }
+
+    Ok(documents)
+}
+
 pub fn from_text_xml(input: &[u8]) -> Result<(NodeCollection, EncodingType)> {
     let mut reader = TextXmlReader::new(input);
     let collection = reader
@@ -73,6 +217,36 @@ pub fn from_text_xml(input: &[u8]) -> Result<(NodeCollection, EncodingType)> {
     Ok((collection, encoding))
 }
 
+pub fn from_text_xml_with_options(
+    options: Options,
+    input: &[u8],
+) -> Result<(NodeCollection, EncodingType)> {
+    let mut reader = TextXmlReader::with_options(input, options);
+    let collection = reader
+        .as_node_collection()?
+        .ok_or(KbinError::NoNodeCollection)?;
+    let encoding = reader.encoding();
+
+    Ok((collection, encoding))
+}
+
+/// Read a text XML document as `encoding`, ignoring the encoding of its XML
+/// declaration. This is for documents without a declaration that are not UTF-8,
+/// or with a declaration that does not match their contents.
+pub fn from_text_xml_with_encoding(
+    input: &[u8],
+    encoding: EncodingType,
+) -> Result<(NodeCollection, EncodingType)> {
+    let mut reader = TextXmlReader::new(input);
+    reader.force_encoding(encoding);
+    let collection = reader
+        .as_node_collection()?
+        .ok_or(KbinError::NoNodeCollection)?;
+    let encoding = reader.encoding();
+
+    Ok((collection, encoding))
+}
+
 pub fn from_bytes(input: Bytes) -> Result<(NodeCollection, EncodingType)> {
     if is_binary_xml(&input) {
         from_binary(input)
@@ -81,6 +255,18 @@ pub fn from_bytes(input: Bytes) -> Result<(NodeCollection, EncodingType)> {
     }
 }
 
+pub fn from_bytes_detect(input: Bytes) -> Result<(NodeCollection, EncodingType, InputFormat)> {
+    if is_binary_xml(&input) {
+        let (collection, encoding) = from_binary(input)?;
+        Ok((collection, encoding, InputFormat::Binary))
+    } else {
+        let (collection, encoding) = from_text_xml(&input)?;
+        Ok((collection, encoding, InputFormat::TextXml))
+    }
+}
+
+/// Read a binary XML document from a byte slice. Unlike `from_binary`, this
+/// copies `input` into a new buffer first.
 #[inline]
 pub fn from_slice(input: &[u8]) -> Result<(NodeCollection, EncodingType)> {
     from_binary(Bytes::from(input.to_vec()))
@@ -102,6 +288,24 @@ where
     writer.to_binary(input).map_err(Into::into)
 }
 
+#[cfg(feature = "rayon")]
+pub fn to_binary_parallel<T>(input: &T) -> Result<Vec<u8>>
+where
+    T: ParallelWriteable + Sync,
+{
+    let mut writer = Writer::new();
+    writer.to_binary_parallel(input).map_err(Into::into)
+}
+
+#[cfg(feature = "rayon")]
+pub fn to_binary_parallel_with_options<T>(options: Options, input: &T) -> Result<Vec<u8>>
+where
+    T: ParallelWriteable + Sync,
+{
+    let mut writer = Writer::with_options(options);
+    writer.to_binary_parallel(input).map_err(Into::into)
+}
+
 pub fn to_text_xml<T>(input: &T) -> Result<Vec<u8>>
 where
     T: ToTextXml,
@@ -110,3 +314,113 @@ where
 
     writer.into_text_xml(input)
 }
+
+pub fn to_text_xml_with_options<T>(options: Options, input: &T) -> Result<Vec<u8>>
+where
+    T: ToTextXml,
+{
+    let writer = TextXmlWriter::with_options(options);
+
+    writer.into_text_xml(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_borrowed() {
+        let input = Bytes::from(to_binary(&Node::with_value("a", Value::U32(1))).unwrap());
+        let (collection, _) = from_binary_borrowed(&input).unwrap();
+
+        // The value data points into the original buffer
+        let value = collection.base().value_bytes().unwrap();
+        let range = input.as_ptr_range();
+        assert!(range.contains(&value.as_ptr()));
+    }
+
+    #[test]
+    fn node_definitions_errors() {
+        let node = Node::with_attrs_value("a", &[("b", "c")], Value::U32(1));
+        let input = to_binary(&node).unwrap();
+
+        let types: Vec<_> = node_definitions(Bytes::from(input.clone()))
+            .unwrap()
+            .map(|definition| definition.unwrap().node_type)
+            .collect();
+        assert_eq!(types, [
+            StandardType::U32,
+            StandardType::Attribute,
+            StandardType::NodeEnd,
+            StandardType::FileEnd
+        ]);
+
+        // Drop the end markers from the node buffer
+        let mut truncated = input;
+        let len_node = BigEndian::read_u32(&truncated[4..8]) as usize;
+        let end = 8 + len_node - 4;
+        truncated[end..8 + len_node].fill(0);
+
+        let definitions: Vec<_> = node_definitions(Bytes::from(truncated)).unwrap().collect();
+        assert!(definitions.last().unwrap().is_err());
+        assert!(definitions[..definitions.len() - 1]
+            .iter()
+            .all(Result::is_ok));
+    }
+
+    #[test]
+    fn binary_multi() {
+        let first = to_binary(&Node::with_value("a", Value::U8(1))).unwrap();
+        let second = to_binary(&Node::with_attrs("b", &[("c", "d")])).unwrap();
+
+        let mut input = first.clone();
+        input.extend_from_slice(&second);
+
+        let documents = from_binary_multi(Bytes::from(input.clone())).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].0.as_node().unwrap().key(), "a");
+        assert_eq!(documents[1].0.as_node().unwrap().key(), "b");
+
+        input.truncate(input.len() - 1);
+        match from_binary_multi(Bytes::from(input)) {
+            Err(KbinError::TruncatedDocument {
+                offset,
+                expected,
+                remaining,
+            }) => {
+                assert_eq!(offset, first.len());
+                assert_eq!(expected, second.len());
+                assert_eq!(remaining, second.len() - 1);
+            },
+            _ => panic!("expected truncated document error"),
+        }
+
+        assert!(from_binary_multi(Bytes::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn binary_with_report() {
+        let mut node = Node::with_attrs("root", &[("note", "a?b")]);
+        node.append_child(Node::with_value("name", Value::String("x?y".into())));
+        node.append_child(Node::with_value("ok", Value::String("fine".into())));
+
+        let mut data = node.to_binary().unwrap();
+        let (_, _, warnings) = from_binary_with_report(Bytes::from(data.clone())).unwrap();
+        assert!(warnings.is_empty());
+
+        // 0xFF is not valid anywhere in Shift-JIS
+        for byte in data.iter_mut().filter(|byte| **byte == b'?') {
+            *byte = 0xFF;
+        }
+        data.extend_from_slice(b"TAIL");
+
+        let (collection, encoding, warnings) = from_binary_with_report(Bytes::from(data)).unwrap();
+        assert_eq!(encoding, EncodingType::SHIFT_JIS);
+        assert_eq!(collection.children().len(), 2);
+        assert_eq!(warnings, vec![
+            ParseWarning::TrailingData { bytes: 4 },
+            ParseWarning::CharacterReplaced { key: "note".into() },
+            ParseWarning::CharacterReplaced { key: "name".into() },
+        ]);
+    }
+}
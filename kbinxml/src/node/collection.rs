@@ -2,10 +2,14 @@ use std::collections::VecDeque;
 use std::fmt;
 use std::iter::{FromIterator, Iterator};
 
+use indexmap::IndexMap;
+
 use crate::error::KbinError;
-use crate::node::{Node, NodeDefinition};
+use crate::node::{Node, NodeDefinition, Schema};
 use crate::node_types::StandardType;
+use crate::options::Options;
 use crate::value::Value;
+use crate::writer::Writer;
 
 fn parse_index(s: &str) -> Option<usize> {
     if s.starts_with('+') || (s.starts_with('0') && s.len() != 1) {
@@ -14,7 +18,35 @@ fn parse_index(s: &str) -> Option<usize> {
     s.parse().ok()
 }
 
+/// Compare two definitions by their decoded key and value. Like `Key`'s
+/// `PartialEq`, the raw bytes are compared if either side fails to decode.
+fn definition_eq(left: &NodeDefinition, right: &NodeDefinition) -> bool {
+    if left.node_type_tuple() != right.node_type_tuple() {
+        return false;
+    }
+
+    let keys_eq = match (left.key(), right.key()) {
+        (Ok(key1), Ok(key2)) => key1 == key2,
+        (_, _) => left.data() == right.data(),
+    };
+    if !keys_eq {
+        return false;
+    }
+
+    if left.node_type == StandardType::NodeStart {
+        return true;
+    }
+
+    match (left.value(), right.value()) {
+        (Ok(value1), Ok(value2)) => value1 == value2,
+        (_, _) => left.value_bytes() == right.value_bytes(),
+    }
+}
+
 /// A collection of node definitions (`NodeDefinition`)
+///
+/// Collections read by `Reader` hold `Bytes` slices of the input buffer, so
+/// cloning one is cheap and does not copy any key or value data.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct NodeCollection {
     base: NodeDefinition,
@@ -50,10 +82,7 @@ impl NodeCollection {
             match def.node_type {
                 StandardType::Attribute => attributes.push_back(def),
                 StandardType::NodeEnd | StandardType::FileEnd => break,
-                _ => match NodeCollection::from_iter_base(def, iter) {
-                    Some(child) => children.push_back(child),
-                    None => return None,
-                },
+                _ => children.push_back(NodeCollection::from_iter_base(def, iter)?),
             }
         }
 
@@ -94,6 +123,126 @@ impl NodeCollection {
         &mut self.children
     }
 
+    /// Keep only the immediate children for which `f` returns `true`, preserving
+    /// their order. Attributes are not passed to `f` and are never removed.
+    pub fn retain_children<F>(&mut self, f: F)
+    where
+        F: FnMut(&NodeCollection) -> bool,
+    {
+        self.children.retain(f);
+    }
+
+    #[inline]
+    pub fn first_child(&self) -> Option<&NodeCollection> {
+        self.children.front()
+    }
+
+    #[inline]
+    pub fn first_child_mut(&mut self) -> Option<&mut NodeCollection> {
+        self.children.front_mut()
+    }
+
+    #[inline]
+    pub fn last_child(&self) -> Option<&NodeCollection> {
+        self.children.back()
+    }
+
+    #[inline]
+    pub fn last_child_mut(&mut self) -> Option<&mut NodeCollection> {
+        self.children.back_mut()
+    }
+
+    /// Iterate over all descendants of this collection in pre-order, not including
+    /// the collection itself.
+    ///
+    /// An explicit stack is used instead of recursion and keys are left undecoded.
+    pub fn iter_descendants(&self) -> impl Iterator<Item = &NodeCollection> {
+        let mut stack: Vec<&NodeCollection> = self.children.iter().rev().collect();
+
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+
+            Some(node)
+        })
+    }
+
+    /// Count the immediate children with the given key.
+    ///
+    /// Child keys are decoded one at a time, returning the first decoding error.
+    pub fn count_children_by_key(&self, key: &str) -> Result<usize, KbinError> {
+        let mut count = 0;
+        for child in &self.children {
+            if child.base.key()?.as_deref() == Some(key) {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Summarize the key paths in this collection along with the node types and
+    /// attribute names observed at each of them.
+    pub fn schema(&self) -> Result<Schema, KbinError> {
+        Schema::from_collection(self)
+    }
+
+    /// Find the first attribute with the given key and decode its value.
+    ///
+    /// Attribute keys are decoded one at a time until a match is found.
+    pub fn attr(&self, key: &str) -> Option<Result<String, KbinError>> {
+        for attr in &self.attributes {
+            match attr.key() {
+                Ok(Some(attr_key)) if attr_key == key => {},
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let value = match attr.value() {
+                Ok(Value::Attribute(value)) => Ok(value),
+                Ok(_) => Err(KbinError::InvalidState),
+                Err(e) => Err(e),
+            };
+
+            return Some(value);
+        }
+
+        None
+    }
+
+    /// Compare two collections by their decoded keys and values, ignoring how they
+    /// are stored. Collections read from documents with different compression or
+    /// encodings compare equal if they hold the same data.
+    ///
+    /// Attributes and children are compared in order.
+    pub fn semantic_eq(&self, other: &NodeCollection) -> bool {
+        definition_eq(&self.base, &other.base) &&
+            self.attributes.len() == other.attributes.len() &&
+            self.children.len() == other.children.len() &&
+            self.attributes
+                .iter()
+                .zip(&other.attributes)
+                .all(|(left, right)| definition_eq(left, right)) &&
+            self.children
+                .iter()
+                .zip(&other.children)
+                .all(|(left, right)| left.semantic_eq(right))
+    }
+
+    /// Encode this collection as binary XML using the default options.
+    pub fn to_binary(&self) -> Result<Vec<u8>, KbinError> {
+        Writer::new().to_binary(self).map_err(Into::into)
+    }
+
+    /// Encode this collection as binary XML using the given options.
+    pub fn to_binary_with_options(&self, options: Options) -> Result<Vec<u8>, KbinError> {
+        Writer::with_options(options)
+            .to_binary(self)
+            .map_err(Into::into)
+    }
+
+    /// Convert this collection into a `Node`, including its attributes and all of
+    /// its descendants.
     pub fn as_node(&self) -> Result<Node, KbinError> {
         let mut node = self.base.as_node()?;
 
@@ -114,6 +263,41 @@ impl NodeCollection {
         Ok(node)
     }
 
+    /// Convert the whole tree into an eager `Node`. This is the same as `as_node`,
+    /// which already converts all descendants.
+    #[inline]
+    pub fn to_node_tree(&self) -> Result<Node, KbinError> {
+        self.as_node()
+    }
+
+    /// Flatten the tree into dotted paths mapped to the text of their values,
+    /// such as `player.name` to `foo`, for diffing or grepping documents.
+    ///
+    /// Paths are built like `Node::paths`, so children sharing a key are
+    /// identified by their index, as in `player.items.0.id`. Attributes follow
+    /// the path of their node with an `@`, as in `player.@id`. Nodes without a
+    /// value are left out.
+    ///
+    /// This is lossy: value types are not kept, and keys containing `.` make
+    /// paths ambiguous.
+    pub fn to_flat_map(&self) -> Result<IndexMap<String, String>, KbinError> {
+        let node = self.as_node()?;
+        let mut map = IndexMap::new();
+
+        for (path, node) in node.paths() {
+            let path = path.join(".");
+
+            for (key, value) in node.attributes() {
+                map.insert(format!("{}.@{}", path, key), value.clone());
+            }
+            if let Some(value) = node.value() {
+                map.insert(path, value.to_string());
+            }
+        }
+
+        Ok(map)
+    }
+
     pub fn pointer<'a>(&'a self, pointer: &[&str]) -> Option<&'a NodeCollection> {
         if pointer.is_empty() {
             return Some(self);
@@ -135,11 +319,7 @@ impl NodeCollection {
                 })
             };
 
-            if let Some(t) = target_opt {
-                target = t;
-            } else {
-                return None;
-            }
+            target = target_opt?;
         }
         Some(target)
     }
@@ -201,3 +381,142 @@ impl fmt::Display for NodeCollection {
         d.finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_node_tree() {
+        let input = br#"
+            <root id="1">
+                <a __type="u8">5</a>
+                <b kind="x">
+                    <c>text</c>
+                    <d __type="s32" __count="2">1 2</d>
+                </b>
+            </root>
+        "#;
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+
+        let expected = Node::with("root", &[("id", "1")], vec![
+            Node::with_value("a", Value::U8(5)),
+            Node::with("b", &[("kind", "x")], vec![
+                Node::with_value("c", Value::String("text".into())),
+                Node::with_value("d", Value::Array(crate::ValueArray::S32(vec![1, 2]))),
+            ]),
+        ]);
+
+        assert_eq!(collection.to_node_tree().unwrap(), expected);
+    }
+
+    #[test]
+    fn semantic_eq() {
+        use crate::{CompressionType, EncodingType};
+
+        let node = Node::with("root", &[("id", "1")], vec![
+            Node::with_value("name", Value::String("abc".into())),
+            Node::with_value("v", Value::S16(-2)),
+        ]);
+        let options = Options::builder()
+            .compression(CompressionType::Uncompressed)
+            .encoding(EncodingType::UTF_8)
+            .build();
+
+        let (compressed, _) = crate::from_slice(&node.to_binary().unwrap()).unwrap();
+        let binary = node.to_binary_with_options(options).unwrap();
+        let (uncompressed, _) = crate::from_slice(&binary).unwrap();
+
+        assert_ne!(compressed, uncompressed);
+        assert!(compressed.semantic_eq(&uncompressed));
+
+        let mut other = node.clone();
+        other.set_attr("id", "2");
+        let (other, _) = crate::from_slice(&other.to_binary().unwrap()).unwrap();
+        assert!(!compressed.semantic_eq(&other));
+    }
+
+    #[test]
+    fn retain_children() {
+        let node = Node::with("root", &[("id", "1")], vec![
+            Node::with_value("a", Value::U8(1)),
+            Node::new("b"),
+            Node::with_value("c", Value::U8(3)),
+        ]);
+        let (mut collection, _) = crate::from_slice(&node.to_binary().unwrap()).unwrap();
+
+        collection.retain_children(|child| {
+            child.base().node_type_tuple() != (StandardType::NodeStart, false)
+        });
+        assert_eq!(collection.attributes().len(), 1);
+
+        collection.retain_children(|child| child.base().key().unwrap().as_deref() != Some("c"));
+        let expected = Node::with("root", &[("id", "1")], vec![Node::with_value(
+            "a",
+            Value::U8(1),
+        )]);
+        assert_eq!(collection.as_node().unwrap(), expected);
+    }
+
+    #[test]
+    fn as_node_keeps_arrays() {
+        use crate::value::ValueArray;
+
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("one", Value::Array(ValueArray::U32(vec![1]))),
+            Node::with_value("empty", Value::Array(ValueArray::S16(vec![]))),
+            Node::with_value(
+                "pairs",
+                Value::Array(ValueArray::U8_2(vec![[1, 2], [3, 4]])),
+            ),
+            Node::with_value("floats", Value::Array(ValueArray::Float4(vec![[0.5; 4]]))),
+            Node::with_value("scalar", Value::U32(1)),
+        ]);
+        let binary = node.to_binary().unwrap();
+
+        let (collection, _) = crate::from_slice(&binary).unwrap();
+        let converted = collection.as_node().unwrap();
+        assert_eq!(converted, node);
+        assert!(matches!(
+            converted.get_child("one").unwrap().value(),
+            Some(Value::Array(ValueArray::U32(_)))
+        ));
+        assert_eq!(converted.to_binary().unwrap(), binary);
+    }
+
+    #[test]
+    fn to_flat_map() {
+        let node = Node::with("player", &[("id", "7")], vec![
+            Node::with_value("name", Value::String("foo".into())),
+            Node::with_nodes("items", vec![
+                Node::with_nodes("item", vec![Node::with_value("id", Value::U32(3))]),
+                Node::with_nodes("item", vec![Node::with_value("id", Value::U32(4))]),
+            ]),
+            Node::with_value("pos", Value::S16_2([1, -2])),
+        ]);
+        let (collection, _) = crate::from_slice(&node.to_binary().unwrap()).unwrap();
+
+        let map = collection.to_flat_map().unwrap();
+        let entries: Vec<_> = map.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(entries, [
+            ("player.@id", "7"),
+            ("player.name", "foo"),
+            ("player.items.0.id", "3"),
+            ("player.items.1.id", "4"),
+            ("player.pos", "1 -2"),
+        ]);
+    }
+
+    #[test]
+    fn count_children_by_key() {
+        let input = b"<root><a/><b/><a/><c><a/></c></root>";
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+        assert_eq!(collection.count_children_by_key("a").unwrap(), 2);
+        assert_eq!(collection.count_children_by_key("d").unwrap(), 0);
+
+        let node = collection.to_node_tree().unwrap();
+        assert_eq!(node.count_children_by_key("a"), 2);
+        assert_eq!(node.count_children_by_key("c"), 1);
+        assert_eq!(node.count_children_by_key("d"), 0);
+    }
+}
@@ -37,9 +37,11 @@ impl Key {
             Key::Compressed { ref size, ref data } => {
                 Sixbit::unpack(data, *size).map_err(Into::into)
             },
-            Key::Uncompressed { encoding, ref data } => {
-                encoding.decode_bytes(data).map_err(Into::into)
-            },
+            // Earlier versions of `Writer` included a null terminator in
+            // uncompressed keys, so a single trailing null is not part of the key
+            Key::Uncompressed { encoding, ref data } => encoding
+                .decode_bytes(data.strip_suffix(&[0]).unwrap_or(data))
+                .map_err(Into::into),
         }
     }
 }
@@ -95,15 +97,39 @@ impl NodeDefinition {
         }
     }
 
+    /// Decode the value of this definition.
+    ///
+    /// All trailing null bytes are removed from `String` and `Attribute` values,
+    /// along with the terminator, so a string that ends in null bytes does not
+    /// round-trip. Null bytes elsewhere in a string are kept. Use
+    /// `value_preserve_nulls` to keep trailing null bytes.
     pub fn value(&self) -> Result<Value, KbinError> {
+        self.value_inner(strip_trailing_null_bytes)
+    }
+
+    /// Like `value`, but `String` and `Attribute` values only have the single
+    /// null terminator removed instead of all trailing null bytes.
+    ///
+    /// This keeps fixed-width fields that end in null bytes intact. Writing the
+    /// value back re-adds the terminator through `EncodingType::encode_bytes`,
+    /// so the original bytes are reproduced.
+    pub fn value_preserve_nulls(&self) -> Result<Value, KbinError> {
+        self.value_inner(|data| data.strip_suffix(&[0]).unwrap_or(data))
+    }
+
+    fn value_inner(&self, strip: fn(&[u8]) -> &[u8]) -> Result<Value, KbinError> {
         match (self.node_type, &self.data) {
+            (
+                node_type @ (StandardType::Attribute | StandardType::String),
+                NodeData::Some { .. },
+            ) if self.is_array => Err(KbinError::NonArrayableType { node_type }),
             (StandardType::Attribute, NodeData::Some { ref value_data, .. }) => {
-                let data = strip_trailing_null_bytes(value_data);
+                let data = strip(value_data);
                 let value = self.encoding.decode_bytes(data)?;
                 Ok(Value::Attribute(value))
             },
             (StandardType::String, NodeData::Some { ref value_data, .. }) => {
-                let data = strip_trailing_null_bytes(value_data);
+                let data = strip(value_data);
                 let value = self.encoding.decode_bytes(data)?;
                 Ok(Value::String(value))
             },
@@ -199,12 +225,9 @@ impl fmt::Display for NodeDefinition {
         let mut d = f.debug_struct("NodeDefinition");
         d.field("node_type", &self.node_type);
 
-        match self.node_type {
-            StandardType::Attribute | StandardType::String => {
-                d.field("encoding", &self.encoding);
-            },
-            _ => {},
-        };
+        if self.node_type.is_textual() {
+            d.field("encoding", &self.encoding);
+        }
 
         match self.data {
             NodeData::Some {
@@ -223,3 +246,72 @@ impl fmt::Display for NodeDefinition {
         d.finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uncompressed_key_null_terminator() {
+        let key = |data: &'static [u8]| {
+            let data = NodeData::Some {
+                key: Key::Uncompressed {
+                    encoding: EncodingType::UTF_8,
+                    data: Bytes::from_static(data),
+                },
+                value_data: Bytes::new(),
+            };
+            NodeDefinition::with_data(EncodingType::UTF_8, StandardType::NodeStart, false, data)
+                .key()
+                .unwrap()
+        };
+
+        assert_eq!(key(b"ab").as_deref(), Some("ab"));
+        assert_eq!(key(b"ab\0").as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn preserve_nulls() {
+        let encoding = EncodingType::UTF_8;
+        let data = NodeData::Some {
+            key: Key::Uncompressed {
+                encoding,
+                data: Bytes::from_static(b"s"),
+            },
+            value_data: Bytes::from_static(b"ab\0\0\0"),
+        };
+        let definition = NodeDefinition::with_data(encoding, StandardType::String, false, data);
+
+        assert_eq!(definition.value().unwrap(), Value::String("ab".into()));
+
+        let value = definition.value_preserve_nulls().unwrap();
+        assert_eq!(value, Value::String("ab\0\0".into()));
+
+        let text = value.as_string().unwrap();
+        assert_eq!(encoding.encode_bytes(&text).unwrap(), b"ab\0\0\0");
+    }
+
+    #[test]
+    fn string_null_bytes() {
+        use crate::node::Node;
+
+        let read = |text: &str| {
+            let node = Node::with_value("s", Value::String(text.into()));
+            let (collection, _) = crate::from_slice(&node.to_binary().unwrap()).unwrap();
+            let base = collection.base();
+
+            (base.value().unwrap(), base.value_preserve_nulls().unwrap())
+        };
+
+        let interior = Value::String("a\0b".into());
+        assert_eq!(read("a\0b"), (interior.clone(), interior));
+
+        let (value, preserved) = read("ab\0");
+        assert_eq!(value, Value::String("ab".into()));
+        assert_eq!(preserved, Value::String("ab\0".into()));
+
+        let (value, preserved) = read("\0");
+        assert_eq!(value, Value::String(String::new()));
+        assert_eq!(preserved, Value::String("\0".into()));
+    }
+}
@@ -1,15 +1,26 @@
-use std::fmt;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::iter;
 use std::mem;
+use std::net::Ipv4Addr;
 
 use indexmap::IndexMap;
 
+use crate::compression_type::CompressionType;
+use crate::encoding_type::EncodingType;
+use crate::error::Result;
+use crate::options::Options;
+use crate::to_text_xml::TextXmlWriter;
 use crate::value::Value;
+use crate::writer::Writer;
 
 mod collection;
 mod definition;
+mod schema;
 
 pub use self::collection::NodeCollection;
 pub use self::definition::{Key, NodeData, NodeDefinition};
+pub use self::schema::{Schema, SchemaEntry};
 
 // The attributes argument is very hard to generalize
 fn convert_attributes(attrs: &[(&str, &str)]) -> IndexMap<String, String> {
@@ -26,6 +37,31 @@ fn parse_index(s: &str) -> Option<usize> {
     s.parse().ok()
 }
 
+/// Controls how `Node::walk` proceeds after visiting a node
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WalkControl {
+    /// Visit the children of the current node, then continue with its siblings
+    Continue,
+
+    /// Do not visit the children of the current node
+    SkipChildren,
+
+    /// Stop the walk entirely
+    Stop,
+}
+
+macro_rules! typed_setters {
+    ($($method:ident($type:ty) => $konst:ident;)+) => {
+        $(
+            #[doc = concat!("Set the value of this node to a `Value::", stringify!($konst), "`.")]
+            #[inline]
+            pub fn $method(&mut self, value: $type) {
+                self.value = Some(Value::$konst(value));
+            }
+        )+
+    };
+}
+
 #[derive(Clone, Default, PartialEq)]
 pub struct Node {
     key: String,
@@ -125,6 +161,23 @@ impl Node {
         }
     }
 
+    /// Build a node named `key` with an `item` child holding each string in
+    /// `items`, in order.
+    ///
+    /// kbin has no arrays of strings, so a list of strings is stored as sibling
+    /// nodes like these rather than as a single array value.
+    pub fn string_list<K>(key: K, items: &[&str]) -> Self
+    where
+        K: Into<String>,
+    {
+        let children: Vec<Node> = items
+            .iter()
+            .map(|item| Node::with_value("item", Value::String((*item).to_owned())))
+            .collect();
+
+        Self::with_nodes(key, children)
+    }
+
     #[inline]
     pub fn key(&self) -> &str {
         &self.key
@@ -140,16 +193,47 @@ impl Node {
         &mut self.attributes
     }
 
+    /// Iterate over the attribute keys of this node, in order.
+    pub fn attribute_keys(&self) -> impl Iterator<Item = &str> {
+        self.attributes.keys().map(String::as_str)
+    }
+
     #[inline]
     pub fn children(&self) -> &[Node] {
         &self.children
     }
 
+    /// Iterate over the keys of the immediate children of this node, in order.
+    /// Keys repeat if there are multiple children with the same key.
+    pub fn child_keys(&self) -> impl Iterator<Item = &str> {
+        self.children.iter().map(Node::key)
+    }
+
     #[inline]
     pub fn children_mut(&mut self) -> &mut Vec<Node> {
         &mut self.children
     }
 
+    #[inline]
+    pub fn first_child(&self) -> Option<&Node> {
+        self.children.first()
+    }
+
+    #[inline]
+    pub fn first_child_mut(&mut self) -> Option<&mut Node> {
+        self.children.first_mut()
+    }
+
+    #[inline]
+    pub fn last_child(&self) -> Option<&Node> {
+        self.children.last()
+    }
+
+    #[inline]
+    pub fn last_child_mut(&mut self) -> Option<&mut Node> {
+        self.children.last_mut()
+    }
+
     #[inline]
     pub fn value(&self) -> Option<&Value> {
         self.value.as_ref()
@@ -160,6 +244,134 @@ impl Node {
         self.value.as_mut()
     }
 
+    /// Returns the encoded bytes of this node's value, as they would be stored
+    /// in the data buffer, or `None` if the node does not have a value.
+    ///
+    /// `String` and `Attribute` values are encoded with the default encoding
+    /// (`EncodingType::SHIFT_JIS`), including the trailing null byte. Use
+    /// `value_bytes_with_encoding` to specify another encoding.
+    pub fn value_bytes(&self) -> Option<Result<Vec<u8>>> {
+        self.value_bytes_with_encoding(EncodingType::default())
+    }
+
+    /// Returns the encoded bytes of this node's value like `value_bytes`, encoding
+    /// `String` and `Attribute` values with the given encoding.
+    pub fn value_bytes_with_encoding(&self, encoding: EncodingType) -> Option<Result<Vec<u8>>> {
+        let value = self.value.as_ref()?;
+
+        let bytes = match value {
+            Value::String(s) | Value::Attribute(s) => encoding.encode_bytes(s).map_err(Into::into),
+            value => value.to_bytes(),
+        };
+
+        Some(bytes)
+    }
+
+    /// Encode this node as binary XML using the default options.
+    pub fn to_binary(&self) -> Result<Vec<u8>> {
+        Writer::new().to_binary(self).map_err(Into::into)
+    }
+
+    /// Encode this node as binary XML using the given options.
+    pub fn to_binary_with_options(&self, options: Options) -> Result<Vec<u8>> {
+        Writer::with_options(options)
+            .to_binary(self)
+            .map_err(Into::into)
+    }
+
+    /// Estimate the size in bytes of this node encoded as binary XML with
+    /// `options`, such as for a size readout in tooling before encoding.
+    ///
+    /// This is an estimate, not a guarantee. Values are measured with
+    /// `Value::encoded_size` and share its limits, padded to the data buffer
+    /// alignment of `options`. 1 and 2 byte values are counted in the shared 4 byte
+    /// slots the writer packs them into. Uncompressed keys are measured in UTF-8
+    /// rather than the target encoding.
+    pub fn encoded_byte_estimate(&self, options: &Options) -> usize {
+        let mut packed = [0; 2];
+        let (node_len, data_len) = self.estimate_buffers(options, &mut packed);
+
+        // Four 1 byte values or two 2 byte values fill a slot, which is padded to
+        // the alignment when it is opened
+        let slot = align_up(4, (options.alignment as usize).max(1));
+        let data_len = data_len + (packed[0].div_ceil(4) + packed[1].div_ceil(2)) * slot;
+
+        // Header, the length of each buffer and the file end marker
+        4 + 4 + align_up(node_len + 1, 4) + 4 + data_len
+    }
+
+    /// Returns the node and data buffer lengths without 1 and 2 byte values,
+    /// which are counted by size in `packed`.
+    fn estimate_buffers(&self, options: &Options, packed: &mut [usize; 2]) -> (usize, usize) {
+        let key_len = |key: &str| match options.compression {
+            CompressionType::Compressed => 1 + (key.len() * 6).div_ceil(8),
+            CompressionType::Uncompressed => 1 + key.len(),
+        };
+
+        // `Value::encoded_size` pads to 4 bytes, which the alignment is a multiple
+        // of
+        let alignment = (options.alignment as usize).max(1);
+        let aligned = |size: usize| align_up(size, alignment);
+
+        // Node type, key and node end
+        let mut node_len = 1 + key_len(&self.key) + 1;
+        let mut data_len = match self.value.as_ref().map(Value::encoded_size) {
+            Some(size @ (1 | 2)) => {
+                packed[size - 1] += 1;
+                0
+            },
+            Some(size) => aligned(size),
+            None => 0,
+        };
+
+        for (key, value) in &self.attributes {
+            node_len += 1 + key_len(key);
+            // Same as `Value::encoded_size` for an attribute
+            data_len += aligned(4 + align_up(value.len() + 1, 4));
+        }
+
+        for child in &self.children {
+            let (child_node_len, child_data_len) = child.estimate_buffers(options, packed);
+            node_len += child_node_len;
+            data_len += child_data_len;
+        }
+
+        (node_len, data_len)
+    }
+
+    /// Convert this node to text XML using the default options.
+    ///
+    /// A `Node` holds decoded strings rather than the bytes of a particular
+    /// encoding, so the output is always UTF-8 with a UTF-8 declaration.
+    ///
+    /// ```
+    /// use kbinxml::{Node, Value};
+    ///
+    /// let mut node = Node::with_attrs("player", &[("id", "1")]);
+    /// node.append_child(Node::with_value("hp", Value::U32(100)));
+    ///
+    /// let text = node.to_text_xml().unwrap();
+    /// assert_eq!(
+    ///     String::from_utf8(text).unwrap(),
+    ///     concat!(
+    ///         "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    ///         "<player id=\"1\">\n",
+    ///         "  <hp __type=\"u32\">100</hp>\n",
+    ///         "</player>",
+    ///     )
+    /// );
+    /// ```
+    pub fn to_text_xml(&self) -> Result<Vec<u8>> {
+        TextXmlWriter::new().into_text_xml(self)
+    }
+
+    /// Convert this node to text XML using the given options. The output and its
+    /// declaration use the encoding in `options`, which is `SHIFT_JIS` unless
+    /// set otherwise.
+    pub fn to_text_xml_with_options(&self, options: Options) -> Result<Vec<u8>> {
+        TextXmlWriter::with_options(options).into_text_xml(self)
+    }
+
     pub fn into_key_value(self) -> (String, Option<Value>) {
         (self.key, self.value)
     }
@@ -179,6 +391,59 @@ impl Node {
         self.attributes.insert(key.into(), value.into())
     }
 
+    /// Set an attribute to the `Display` representation of `value`, such as a
+    /// count or an id. kbin attributes are always stored as strings, so the value
+    /// is formatted when it is set. Returns the previous value, if any.
+    pub fn set_attr_display<K, V>(&mut self, key: K, value: V) -> Option<String>
+    where
+        K: Into<String>,
+        V: fmt::Display,
+    {
+        self.set_attr(key, value.to_string())
+    }
+
+    /// Remove the attribute with the given key, preserving the order of the
+    /// remaining attributes.
+    pub fn remove_attr(&mut self, key: &str) -> Option<String> {
+        self.attributes.shift_remove(key)
+    }
+
+    /// Remove all attributes for which `f` returns `true`, preserving the order
+    /// of the remaining attributes. Returns the number of attributes removed.
+    pub fn remove_attrs_matching<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&str, &str) -> bool,
+    {
+        let len = self.attributes.len();
+        self.attributes.retain(|key, value| !f(key, value));
+
+        len - self.attributes.len()
+    }
+
+    /// Copy the attributes of `other` into this node. New keys are appended and
+    /// existing keys are updated in place if `overwrite` is `true`, otherwise
+    /// they are left unchanged. Returns the number of attributes added or changed.
+    pub fn merge_attributes_from(&mut self, other: &Node, overwrite: bool) -> usize {
+        let mut changed = 0;
+
+        for (key, value) in &other.attributes {
+            match self.attributes.get_mut(key) {
+                Some(existing) => {
+                    if overwrite && existing != value {
+                        existing.clone_from(value);
+                        changed += 1;
+                    }
+                },
+                None => {
+                    self.attributes.insert(key.clone(), value.clone());
+                    changed += 1;
+                },
+            }
+        }
+
+        changed
+    }
+
     pub fn sort_attrs(&mut self) {
         self.attributes.sort_keys();
     }
@@ -191,6 +456,31 @@ impl Node {
         mem::replace(&mut self.value, value)
     }
 
+    typed_setters! {
+        set_s8(i8) => S8;
+        set_u8(u8) => U8;
+        set_s16(i16) => S16;
+        set_u16(u16) => U16;
+        set_s32(i32) => S32;
+        set_u32(u32) => U32;
+        set_s64(i64) => S64;
+        set_u64(u64) => U64;
+        set_bool(bool) => Boolean;
+        set_float(f32) => Float;
+        set_double(f64) => Double;
+        set_binary(Vec<u8>) => Binary;
+        set_ip4(Ipv4Addr) => Ip4;
+    }
+
+    /// Set the value of this node to a `Value::String`.
+    #[inline]
+    pub fn set_string<S>(&mut self, value: S)
+    where
+        S: Into<String>,
+    {
+        self.value = Some(Value::String(value.into()));
+    }
+
     pub fn has(&self, key: &str) -> bool {
         self.children.iter().any(|node| node.key == key)
     }
@@ -203,6 +493,26 @@ impl Node {
         self.children.iter_mut().find(|node| node.key == key)
     }
 
+    /// Iterate over the immediate children with the given key, in order.
+    pub fn children_by_key<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Node> {
+        self.children.iter().filter(move |child| child.key == key)
+    }
+
+    /// Iterate mutably over the immediate children with the given key, in order.
+    pub fn children_by_key_mut<'a>(
+        &'a mut self,
+        key: &'a str,
+    ) -> impl Iterator<Item = &'a mut Node> {
+        self.children
+            .iter_mut()
+            .filter(move |child| child.key == key)
+    }
+
+    /// Count the immediate children with the given key without collecting them.
+    pub fn count_children_by_key(&self, key: &str) -> usize {
+        self.children_by_key(key).count()
+    }
+
     pub fn remove_child(&mut self, key: &str) -> Option<Node> {
         if let Some(index) = self.children.iter().position(|node| node.key == key) {
             Some(self.children.remove(index))
@@ -211,6 +521,42 @@ impl Node {
         }
     }
 
+    /// Replace the first child with the given key by `new`, keeping its position,
+    /// and return the old child. If there is no such child, `new` is appended when
+    /// `append` is `true` and dropped otherwise.
+    pub fn replace_child(&mut self, key: &str, new: Node, append: bool) -> Option<Node> {
+        match self.children.iter_mut().find(|node| node.key == key) {
+            Some(child) => Some(mem::replace(child, new)),
+            None => {
+                if append {
+                    self.children.push(new);
+                }
+                None
+            },
+        }
+    }
+
+    /// Recursively remove children that have no value, no attributes and no
+    /// children left after their own empty children were removed. `self` is
+    /// never removed. Returns the number of nodes removed.
+    pub fn prune_empty(&mut self) -> usize {
+        let mut pruned = 0;
+
+        self.children.retain_mut(|child| {
+            pruned += child.prune_empty();
+
+            let empty =
+                child.value.is_none() && child.attributes.is_empty() && child.children.is_empty();
+            if empty {
+                pruned += 1;
+            }
+
+            !empty
+        });
+
+        pruned
+    }
+
     pub fn pointer<'a>(&'a self, pointer: &[&str]) -> Option<&'a Node> {
         if pointer.is_empty() {
             return Some(self);
@@ -225,16 +571,19 @@ impl Node {
                 target.children.iter().find(|child| child.key == *token)
             };
 
-            if let Some(t) = target_opt {
-                target = t;
-            } else {
-                return None;
-            }
+            target = target_opt?;
         }
 
         Some(target)
     }
 
+    /// Return an owned deep clone of the subtree at `pointer`, or `None` if
+    /// there is no node at that path. Only the subtree is cloned.
+    #[inline]
+    pub fn clone_subtree(&self, pointer: &[&str]) -> Option<Node> {
+        self.pointer(pointer).cloned()
+    }
+
     pub fn pointer_mut<'a>(&'a mut self, pointer: &[&str]) -> Option<&'a mut Node> {
         if pointer.is_empty() {
             return Some(self);
@@ -249,13 +598,529 @@ impl Node {
                 target.children.iter_mut().find(|child| child.key == *token)
             };
 
-            if let Some(t) = target_opt {
-                target = t;
+            target = target_opt?;
+        }
+
+        Some(target)
+    }
+
+    /// Visit this node and its descendants in pre-order, calling `f` with each
+    /// node and its depth, starting at 0 for `self`. The return value of `f`
+    /// decides whether the children of a node are visited or the walk stops.
+    ///
+    /// An explicit stack is used instead of recursion.
+    pub fn walk<'a, F>(&'a self, mut f: F)
+    where
+        F: FnMut(&'a Node, usize) -> WalkControl,
+    {
+        let mut stack = vec![(self, 0)];
+
+        while let Some((node, depth)) = stack.pop() {
+            match f(node, depth) {
+                WalkControl::Continue => {
+                    stack.extend(node.children.iter().rev().map(|child| (child, depth + 1)));
+                },
+                WalkControl::SkipChildren => {},
+                WalkControl::Stop => break,
+            }
+        }
+    }
+
+    /// Iterate over this node and its descendants in pre-order, along with the
+    /// path of keys leading to each node, starting with the key of `self`.
+    ///
+    /// A child whose key is shared with a sibling is identified by its index
+    /// among all of its parent's children instead of its key, such as
+    /// `["player", "items", "0", "id"]`. Paths without the first element can be
+    /// passed to `pointer` to find the same node again.
+    pub fn paths(&self) -> impl Iterator<Item = (Vec<String>, &Node)> {
+        let mut stack = vec![(vec![self.key.clone()], self)];
+
+        iter::from_fn(move || {
+            let (path, node) = stack.pop()?;
+
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for child in &node.children {
+                *counts.entry(child.key()).or_insert(0) += 1;
+            }
+
+            for (index, child) in node.children.iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                if counts[child.key()] > 1 {
+                    child_path.push(index.to_string());
+                } else {
+                    child_path.push(child.key.clone());
+                }
+                stack.push((child_path, child));
+            }
+
+            Some((path, node))
+        })
+    }
+
+    /// Format this node and its descendants as an indented tree that shows the
+    /// type of every value, such as `hp: U32(100)`, and the element type and
+    /// length of arrays, such as `scores: Array<S32; 3>(1 2 3)`. Attributes are
+    /// shown as `@key = "value"` lines below their node.
+    pub fn debug_typed(&self) -> String {
+        let mut output = String::new();
+
+        self.walk(|node, depth| {
+            let indent = "  ".repeat(depth);
+
+            // Writing to a `String` cannot fail
+            let _ = match node.value {
+                Some(Value::Array(ref values)) => writeln!(
+                    output,
+                    "{}{}: Array<{:?}; {}>({})",
+                    indent,
+                    node.key,
+                    values.standard_type(),
+                    values.len(),
+                    values
+                ),
+                Some(ref value) => writeln!(output, "{}{}: {:?}", indent, node.key, value),
+                None => writeln!(output, "{}{}", indent, node.key),
+            };
+            for (key, value) in &node.attributes {
+                let _ = writeln!(output, "{}  @{} = {:?}", indent, key, value);
+            }
+
+            WalkControl::Continue
+        });
+
+        output
+    }
+
+    fn has_attr(&self, attr: &str, value: &str) -> bool {
+        self.attributes.get(attr).map(String::as_str) == Some(value)
+    }
+
+    /// Find the first descendant, in pre-order, whose attribute `attr` equals
+    /// `value`, such as the entry of a list with a given `id`. `self` is not
+    /// included in the search.
+    pub fn find_by_attr(&self, attr: &str, value: &str) -> Option<&Node> {
+        let mut found = None;
+        self.walk(|node, depth| {
+            if depth > 0 && node.has_attr(attr, value) {
+                found = Some(node);
+                WalkControl::Stop
             } else {
-                return None;
+                WalkControl::Continue
+            }
+        });
+
+        found
+    }
+
+    /// Mutable version of `find_by_attr`.
+    pub fn find_by_attr_mut(&mut self, attr: &str, value: &str) -> Option<&mut Node> {
+        let mut stack: Vec<&mut Node> = self.children.iter_mut().rev().collect();
+
+        while let Some(node) = stack.pop() {
+            if node.has_attr(attr, value) {
+                return Some(node);
             }
+            stack.extend(node.children.iter_mut().rev());
         }
 
-        Some(target)
+        None
+    }
+
+    /// Find every descendant, in pre-order, whose attribute `attr` equals
+    /// `value`. `self` is not included in the search.
+    pub fn find_all_by_attr(&self, attr: &str, value: &str) -> Vec<&Node> {
+        let mut found = Vec::new();
+        self.walk(|node, depth| {
+            if depth > 0 && node.has_attr(attr, value) {
+                found.push(node);
+            }
+            WalkControl::Continue
+        });
+
+        found
+    }
+
+    /// Mutable version of `find_all_by_attr`. The descendants of a matching node
+    /// are not searched, as they are already reachable through it.
+    pub fn find_all_by_attr_mut(&mut self, attr: &str, value: &str) -> Vec<&mut Node> {
+        let mut found = Vec::new();
+        let mut stack: Vec<&mut Node> = self.children.iter_mut().rev().collect();
+
+        while let Some(node) = stack.pop() {
+            if node.has_attr(attr, value) {
+                found.push(node);
+            } else {
+                stack.extend(node.children.iter_mut().rev());
+            }
+        }
+
+        found
+    }
+
+    /// Compare two nodes while ignoring the order of children and attributes.
+    ///
+    /// Children are matched as a multiset, each child of `self` being paired with
+    /// an unmatched child of `other` that is itself equal under this comparison.
+    /// This is O(n·m) in the number of children at each level, so it is intended
+    /// for tests rather than large documents.
+    pub fn eq_unordered(&self, other: &Node) -> bool {
+        // `IndexMap` equality does not depend on insertion order
+        if self.key != other.key ||
+            self.value != other.value ||
+            self.attributes != other.attributes ||
+            self.children.len() != other.children.len()
+        {
+            return false;
+        }
+
+        let mut matched = vec![false; other.children.len()];
+
+        self.children.iter().all(|child| {
+            let found = other
+                .children
+                .iter()
+                .enumerate()
+                .position(|(i, candidate)| !matched[i] && child.eq_unordered(candidate));
+
+            match found {
+                Some(i) => {
+                    matched[i] = true;
+                    true
+                },
+                None => false,
+            }
+        })
+    }
+}
+
+fn align_up(len: usize, alignment: usize) -> usize {
+    len.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, value: u8) -> Node {
+        Node::with("entry", &[("id", id), ("kind", "fruit")], vec![
+            Node::with_value("v", Value::U8(value)),
+        ])
+    }
+
+    #[test]
+    fn eq_unordered_reordered_siblings() {
+        let left = Node::with_nodes("root", vec![entry("1", 1), entry("2", 2), entry("2", 2)]);
+        let right = Node::with_nodes("root", vec![entry("2", 2), entry("1", 1), entry("2", 2)]);
+
+        assert_ne!(left, right);
+        assert!(left.eq_unordered(&right));
+        assert!(right.eq_unordered(&left));
+    }
+
+    #[test]
+    fn eq_unordered_attribute_order() {
+        let left = Node::with_attrs("n", &[("a", "1"), ("b", "2")]);
+        let right = Node::with_attrs("n", &[("b", "2"), ("a", "1")]);
+
+        assert!(left.eq_unordered(&right));
+    }
+
+    #[test]
+    fn remove_attrs_matching() {
+        let mut node = Node::with_attrs("n", &[("x_a", "1"), ("b", "2"), ("x_c", "3"), ("d", "4")]);
+
+        assert_eq!(
+            node.remove_attrs_matching(|key, _| key.starts_with("x_")),
+            2
+        );
+
+        let keys: Vec<_> = node.attributes().keys().collect();
+        assert_eq!(keys, ["b", "d"]);
+        assert_eq!(
+            node.remove_attrs_matching(|key, _| key.starts_with("x_")),
+            0
+        );
+    }
+
+    #[test]
+    fn walk_control() {
+        let root = Node::with_nodes("root", vec![
+            Node::with_nodes("a", vec![Node::new("a1"), Node::new("a2")]),
+            Node::with_nodes("b", vec![Node::new("b1")]),
+            Node::new("c"),
+        ]);
+
+        let mut visited = Vec::new();
+        root.walk(|node, depth| {
+            visited.push((node.key().to_owned(), depth));
+            match node.key() {
+                "a" => WalkControl::SkipChildren,
+                "b1" => WalkControl::Stop,
+                _ => WalkControl::Continue,
+            }
+        });
+
+        let visited: Vec<_> = visited.iter().map(|(k, d)| (k.as_str(), *d)).collect();
+        assert_eq!(visited, [("root", 0), ("a", 1), ("b", 1), ("b1", 2)]);
+    }
+
+    #[test]
+    fn typed_setters() {
+        let mut node = Node::new("n");
+
+        node.set_u32(7);
+        assert_eq!(node.value(), Some(&Value::U32(7)));
+        node.set_string("text");
+        assert_eq!(node.value(), Some(&Value::String("text".into())));
+        node.set_binary(vec![1, 2]);
+        assert_eq!(node.value(), Some(&Value::Binary(vec![1, 2])));
+    }
+
+    #[test]
+    fn prune_empty() {
+        let mut root = Node::with_nodes("root", vec![
+            Node::with_nodes("a", vec![Node::with_nodes("a1", vec![Node::new("a2")])]),
+            Node::with_nodes("b", vec![
+                Node::new("b1"),
+                Node::with_value("b2", Value::U8(1)),
+            ]),
+            Node::with_attrs("c", &[("id", "1")]),
+            Node::new("d"),
+        ]);
+
+        assert_eq!(root.prune_empty(), 5);
+        assert_eq!(
+            root,
+            Node::with_nodes("root", vec![
+                Node::with_nodes("b", vec![Node::with_value("b2", Value::U8(1))]),
+                Node::with_attrs("c", &[("id", "1")]),
+            ])
+        );
+        assert_eq!(root.prune_empty(), 0);
+    }
+
+    #[test]
+    fn eq_unordered_multiset() {
+        let left = Node::with_nodes("root", vec![entry("1", 1), entry("1", 1), entry("2", 2)]);
+        let right = Node::with_nodes("root", vec![entry("1", 1), entry("2", 2), entry("2", 2)]);
+        assert!(!left.eq_unordered(&right));
+
+        let nested = Node::with_nodes("root", vec![entry("1", 1), entry("2", 3)]);
+        let other = Node::with_nodes("root", vec![entry("2", 2), entry("1", 1)]);
+        assert!(!nested.eq_unordered(&other));
+    }
+
+    #[test]
+    fn merge_attributes_from() {
+        let patch = Node::with_attrs("patch", &[("b", "3"), ("c", "4"), ("a", "1")]);
+
+        let mut node = Node::with_attrs("node", &[("a", "1"), ("b", "2")]);
+        assert_eq!(node.merge_attributes_from(&patch, false), 1);
+        let attrs: Vec<_> = node
+            .attributes()
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(attrs, [("a", "1"), ("b", "2"), ("c", "4")]);
+
+        let mut node = Node::with_attrs("node", &[("a", "1"), ("b", "2")]);
+        assert_eq!(node.merge_attributes_from(&patch, true), 2);
+        let attrs: Vec<_> = node.attributes().values().map(String::as_str).collect();
+        assert_eq!(attrs, ["1", "3", "4"]);
+    }
+
+    #[test]
+    fn attribute_and_child_keys() {
+        let node = Node::with("root", &[("b", "1"), ("a", "2")], vec![
+            Node::new("y"),
+            Node::new("x"),
+            Node::new("y"),
+        ]);
+
+        assert_eq!(node.attribute_keys().collect::<Vec<_>>(), ["b", "a"]);
+        assert_eq!(node.child_keys().collect::<Vec<_>>(), ["y", "x", "y"]);
+        assert_eq!(Node::new("empty").child_keys().count(), 0);
+    }
+
+    #[test]
+    fn find_by_attr() {
+        let mut root = Node::with_attrs("root", &[("id", "1")]);
+        let mut list = Node::new("list");
+        list.append_child(entry("1", 10));
+        list.append_child(entry("2", 20));
+        list.append_child(entry("1", 30));
+        root.append_child(list);
+
+        let found = root.find_by_attr("id", "1").unwrap();
+        assert_eq!(found.get_child("v").unwrap().value(), Some(&Value::U8(10)));
+        assert_eq!(root.find_all_by_attr("id", "1").len(), 2);
+        assert!(root.find_by_attr("id", "3").is_none());
+        assert!(root.find_all_by_attr("missing", "1").is_empty());
+
+        root.find_by_attr_mut("id", "2")
+            .unwrap()
+            .set_attr("id", "3");
+        assert!(root.find_by_attr("id", "3").is_some());
+
+        for node in root.find_all_by_attr_mut("id", "1") {
+            node.set_attr("seen", "1");
+        }
+        assert_eq!(root.find_all_by_attr("seen", "1").len(), 2);
+    }
+
+    #[test]
+    fn debug_typed() {
+        use crate::value::ValueArray;
+
+        let node = Node::with("player", &[("id", "1")], vec![
+            Node::with_value("name", Value::String("player1".into())),
+            Node::with_value("hp", Value::U32(100)),
+            Node::with_value("scores", Value::Array(ValueArray::S32(vec![1, 2, 3]))),
+            Node::with_nodes("empty", vec![]),
+        ]);
+
+        assert_eq!(
+            node.debug_typed(),
+            concat!(
+                "player\n",
+                "  @id = \"1\"\n",
+                "  name: String(\"player1\")\n",
+                "  hp: U32(100)\n",
+                "  scores: Array<S32; 3>(1 2 3)\n",
+                "  empty\n",
+            )
+        );
+    }
+
+    #[test]
+    fn set_attr_display() {
+        let mut node = Node::new("entry");
+        assert_eq!(node.set_attr_display("count", 3u32), None);
+        assert_eq!(node.set_attr_display("ratio", 0.5f32), None);
+        assert_eq!(node.set_attr_display("count", -4i64), Some("3".into()));
+
+        assert_eq!(node.attributes()["count"], "-4");
+        assert_eq!(node.attributes()["ratio"], "0.5");
+    }
+
+    #[test]
+    fn encoded_byte_estimate() {
+        use crate::value::ValueArray;
+
+        let node = Node::with("player", &[("id", "1")], vec![
+            Node::with_value("name", Value::String("player1".into())),
+            Node::with_value("hp", Value::U32(100)),
+            Node::with_value("pos", Value::Double2([1.0, 2.0])),
+            Node::with_value("scores", Value::Array(ValueArray::S32(vec![1, 2, 3]))),
+            Node::with_value("data", Value::Binary(vec![1, 2, 3, 4, 5])),
+            Node::new("empty"),
+        ]);
+
+        let aligned = Options::builder().alignment(16).build();
+        for options in [
+            Options::konami_default(),
+            Options::utf8_uncompressed(),
+            aligned,
+        ] {
+            let len = node.to_binary_with_options(options.clone()).unwrap().len();
+            assert_eq!(node.encoded_byte_estimate(&options), len);
+        }
+
+        // 1 and 2 byte values share slots, including one left partly filled
+        let node = Node::with_nodes("flags", vec![
+            Node::with_value("a", Value::U8(1)),
+            Node::with_value("b", Value::U16(2)),
+            Node::with_value("c", Value::U32(3)),
+            Node::with_value("d", Value::Boolean(true)),
+            Node::with_value("e", Value::S8_2([4, 5])),
+            Node::with_value("f", Value::U16(6)),
+            Node::with_value("g", Value::U8_3([7, 8, 9])),
+            Node::with_value("h", Value::S8(-1)),
+            Node::with_value("i", Value::U8(1)),
+            Node::with_value("j", Value::U8(1)),
+        ]);
+        for options in [Options::default(), Options::builder().alignment(16).build()] {
+            let len = node.to_binary_with_options(options.clone()).unwrap().len();
+            assert_eq!(node.encoded_byte_estimate(&options), len);
+        }
+    }
+
+    #[test]
+    fn to_text_xml_with_options() {
+        let node = Node::with_attrs_value("名前", &[("id", "1")], Value::String("日本".into()));
+
+        let text = node.to_text_xml_with_options(Options::default()).unwrap();
+        assert!(text.starts_with(b"<?xml version=\"1.0\" encoding=\"Shift_JIS\"?>"));
+        assert!(std::str::from_utf8(&text).is_err());
+
+        let (collection, encoding) = crate::from_text_xml(&text).unwrap();
+        assert_eq!(encoding, EncodingType::SHIFT_JIS);
+        assert_eq!(collection.as_node().unwrap(), node);
+
+        // The free function and the text of a decoded collection agree
+        assert_eq!(
+            crate::to_text_xml_with_options(Options::default(), &node).unwrap(),
+            text
+        );
+        let options = Options::new(CompressionType::Uncompressed, EncodingType::SHIFT_JIS);
+        let binary = node.to_binary_with_options(options).unwrap();
+        let (collection, _) = crate::from_slice(&binary).unwrap();
+        assert_eq!(
+            crate::to_text_xml_with_options(Options::default(), &collection).unwrap(),
+            text
+        );
+
+        let options = Options::with_encoding(EncodingType::UTF_8);
+        let text = node.to_text_xml_with_options(options).unwrap();
+        assert_eq!(text, node.to_text_xml().unwrap());
+    }
+
+    #[test]
+    fn replace_child() {
+        let mut node = Node::with_nodes("root", vec![
+            entry("1", 1),
+            Node::with_value("v", Value::U8(2)),
+            entry("3", 3),
+        ]);
+
+        let old = node.replace_child("entry", entry("4", 4), false);
+        assert_eq!(old, Some(entry("1", 1)));
+        assert_eq!(node.children()[0], entry("4", 4));
+        assert_eq!(node.children()[2], entry("3", 3));
+
+        assert_eq!(node.replace_child("missing", Node::new("a"), false), None);
+        assert_eq!(node.children().len(), 3);
+
+        assert_eq!(node.replace_child("missing", Node::new("a"), true), None);
+        assert_eq!(node.children().len(), 4);
+        assert_eq!(node.last_child().unwrap().key(), "a");
+    }
+
+    #[test]
+    fn paths() {
+        let node = Node::with_nodes("player", vec![
+            Node::with_value("name", Value::String("foo".into())),
+            Node::with_nodes("items", vec![entry("1", 1), entry("2", 2)]),
+        ]);
+
+        let paths: Vec<_> = node
+            .paths()
+            .map(|(path, child)| (path.join("."), child.key()))
+            .collect();
+        assert_eq!(paths, [
+            ("player".to_owned(), "player"),
+            ("player.name".to_owned(), "name"),
+            ("player.items".to_owned(), "items"),
+            ("player.items.0".to_owned(), "entry"),
+            ("player.items.0.v".to_owned(), "v"),
+            ("player.items.1".to_owned(), "entry"),
+            ("player.items.1.v".to_owned(), "v"),
+        ]);
+
+        for (path, child) in node.paths() {
+            let pointer: Vec<_> = path[1..].iter().map(String::as_str).collect();
+            assert!(std::ptr::eq(node.pointer(&pointer).unwrap(), child));
+        }
     }
 }
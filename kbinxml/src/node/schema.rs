@@ -0,0 +1,115 @@
+use indexmap::IndexMap;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+use crate::error::KbinError;
+use crate::node::NodeCollection;
+use crate::node_types::StandardType;
+
+/// A summary of the shape of a document, built by `NodeCollection::schema`.
+///
+/// Entries are keyed by the `/` separated path of node keys from the root and are
+/// kept in the order they were first seen.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Schema {
+    pub entries: IndexMap<String, SchemaEntry>,
+}
+
+/// What was observed for every node found at a single key path.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SchemaEntry {
+    /// Number of nodes found at this path
+    pub occurrences: usize,
+
+    /// Node types observed, in the order they were first seen
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_types"))]
+    pub types: Vec<StandardType>,
+
+    /// Set if any node at this path was an array
+    pub is_array: bool,
+
+    /// Attribute names observed, in the order they were first seen
+    pub attributes: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+fn serialize_types<S>(types: &[StandardType], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(types.iter().map(|node_type| node_type.name))
+}
+
+impl Schema {
+    pub(crate) fn from_collection(collection: &NodeCollection) -> Result<Self, KbinError> {
+        let mut schema = Schema::default();
+        let mut stack = vec![(String::new(), collection)];
+
+        while let Some((parent, collection)) = stack.pop() {
+            let base = collection.base();
+            let key = base.key()?.ok_or(KbinError::InvalidState)?;
+            let path = if parent.is_empty() {
+                key
+            } else {
+                format!("{}/{}", parent, key)
+            };
+
+            let entry = schema.entries.entry(path.clone()).or_default();
+            let (node_type, is_array) = base.node_type_tuple();
+
+            entry.occurrences += 1;
+            entry.is_array |= is_array;
+            if !entry.types.contains(&node_type) {
+                entry.types.push(node_type);
+            }
+
+            for attr in collection.attributes() {
+                let name = attr.key()?.ok_or(KbinError::InvalidState)?;
+                if !entry.attributes.contains(&name) {
+                    entry.attributes.push(name);
+                }
+            }
+
+            stack.extend(
+                collection
+                    .children()
+                    .iter()
+                    .rev()
+                    .map(|child| (path.clone(), child)),
+            );
+        }
+
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_paths() {
+        let input = br#"
+            <root>
+                <item id="1"><v __type="u8">1</v></item>
+                <item id="2" name="b"><v __type="s32" __count="2">1 2</v></item>
+            </root>
+        "#;
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+        let schema = collection.schema().unwrap();
+
+        let paths: Vec<_> = schema.entries.keys().collect();
+        assert_eq!(paths, ["root", "root/item", "root/item/v"]);
+
+        let item = &schema.entries["root/item"];
+        assert_eq!(item.occurrences, 2);
+        assert_eq!(item.attributes, ["id", "name"]);
+
+        let v = &schema.entries["root/item/v"];
+        assert_eq!(v.types, [StandardType::U8, StandardType::S32]);
+        assert!(v.is_array);
+    }
+}
@@ -91,11 +91,13 @@ macro_rules! construct_types {
       }
     }
 
+    /// Displays the type name used by Konami's XML tooling (the `__type`
+    /// attribute value), such as `s32`, `3u8`, `bin` or `str`.
     impl fmt::Display for StandardType {
       fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
           $(
-            StandardType::$konst => f.write_str(stringify!($konst)),
+            StandardType::$konst => f.write_str($name),
           )+
         }
       }
@@ -178,3 +180,172 @@ construct_types! {
   (190, NODE_END, NodeEnd, "nodeEnd", None, 0, 0);
   (191, FILE_END, FileEnd, "fileEnd", None, 0, 0);
 }
+
+/// Broad category of a `StandardType`'s value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TypeClass {
+    Integer,
+    Floating,
+    Textual,
+    Boolean,
+    Other,
+}
+
+impl StandardType {
+    fn class(&self) -> TypeClass {
+        use StandardType::*;
+
+        // No wildcard arm so that a new type must be categorized here
+        match *self {
+            S8 | U8 | S16 | U16 | S32 | U32 | S64 | U64 => TypeClass::Integer,
+            S8_2 | U8_2 | S16_2 | U16_2 | S32_2 | U32_2 | S64_2 | U64_2 => TypeClass::Integer,
+            S8_3 | U8_3 | S16_3 | U16_3 | S32_3 | U32_3 | S64_3 | U64_3 => TypeClass::Integer,
+            S8_4 | U8_4 | S16_4 | U16_4 | S32_4 | U32_4 | S64_4 | U64_4 => TypeClass::Integer,
+            Vs8 | Vu8 | Vs16 | Vu16 => TypeClass::Integer,
+            Float | Float2 | Float3 | Float4 => TypeClass::Floating,
+            Double | Double2 | Double3 | Double4 => TypeClass::Floating,
+            String | Attribute => TypeClass::Textual,
+            Binary | Ip4 | Time => TypeClass::Other,
+            Boolean | Boolean2 | Boolean3 | Boolean4 | Vb => TypeClass::Boolean,
+            NodeStart | NodeEnd | FileEnd => TypeClass::Other,
+        }
+    }
+
+    /// Returns `true` for types whose value is stored as encoded text
+    /// (`String` and `Attribute`).
+    pub fn is_textual(&self) -> bool {
+        self.class() == TypeClass::Textual
+    }
+
+    /// Returns `true` for integer and floating point types, including their
+    /// multi-element forms. `Time`, `Ip4` and the boolean types are not numeric.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self.class(), TypeClass::Integer | TypeClass::Floating)
+    }
+
+    /// Returns `true` for the `float` and `double` types and their multi-element
+    /// forms.
+    pub fn is_floating(&self) -> bool {
+        self.class() == TypeClass::Floating
+    }
+
+    /// Returns `true` for the `bool` type and its multi-element forms.
+    pub fn is_boolean(&self) -> bool {
+        self.class() == TypeClass::Boolean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StandardType;
+
+    const NAMES: &[(StandardType, &str)] = &[
+        (StandardType::NodeStart, "void"),
+        (StandardType::S8, "s8"),
+        (StandardType::U8, "u8"),
+        (StandardType::S16, "s16"),
+        (StandardType::U16, "u16"),
+        (StandardType::S32, "s32"),
+        (StandardType::U32, "u32"),
+        (StandardType::S64, "s64"),
+        (StandardType::U64, "u64"),
+        (StandardType::Binary, "bin"),
+        (StandardType::String, "str"),
+        (StandardType::Ip4, "ip4"),
+        (StandardType::Time, "time"),
+        (StandardType::Float, "float"),
+        (StandardType::Double, "double"),
+        (StandardType::S8_2, "2s8"),
+        (StandardType::U8_2, "2u8"),
+        (StandardType::S16_2, "2s16"),
+        (StandardType::U16_2, "2u16"),
+        (StandardType::S32_2, "2s32"),
+        (StandardType::U32_2, "2u32"),
+        (StandardType::S64_2, "2s64"),
+        (StandardType::U64_2, "2u64"),
+        (StandardType::Float2, "2f"),
+        (StandardType::Double2, "2d"),
+        (StandardType::S8_3, "3s8"),
+        (StandardType::U8_3, "3u8"),
+        (StandardType::S16_3, "3s16"),
+        (StandardType::U16_3, "3u16"),
+        (StandardType::S32_3, "3s32"),
+        (StandardType::U32_3, "3u32"),
+        (StandardType::S64_3, "3s64"),
+        (StandardType::U64_3, "3u64"),
+        (StandardType::Float3, "3f"),
+        (StandardType::Double3, "3d"),
+        (StandardType::S8_4, "4s8"),
+        (StandardType::U8_4, "4u8"),
+        (StandardType::S16_4, "4s16"),
+        (StandardType::U16_4, "4u16"),
+        (StandardType::S32_4, "4s32"),
+        (StandardType::U32_4, "4u32"),
+        (StandardType::S64_4, "4s64"),
+        (StandardType::U64_4, "4u64"),
+        (StandardType::Float4, "4f"),
+        (StandardType::Double4, "4d"),
+        (StandardType::Attribute, "attr"),
+        (StandardType::Vs8, "vs8"),
+        (StandardType::Vu8, "vu8"),
+        (StandardType::Vs16, "vs16"),
+        (StandardType::Vu16, "vu16"),
+        (StandardType::Boolean, "bool"),
+        (StandardType::Boolean2, "2b"),
+        (StandardType::Boolean3, "3b"),
+        (StandardType::Boolean4, "4b"),
+        (StandardType::Vb, "vb"),
+        (StandardType::NodeEnd, "nodeEnd"),
+        (StandardType::FileEnd, "fileEnd"),
+    ];
+
+    #[test]
+    fn test_display_names() {
+        for (node_type, name) in NAMES {
+            assert_eq!(node_type.to_string(), *name, "{:?}", node_type);
+            assert_eq!(StandardType::from_name(name).unwrap(), *node_type);
+        }
+
+        // Every type id must be covered by the table above.
+        let count = (0..=u8::MAX)
+            .filter(|id| StandardType::from_u8(*id).is_ok())
+            .count();
+        assert_eq!(count, NAMES.len());
+    }
+
+    #[test]
+    fn predicates() {
+        use StandardType::*;
+
+        const INTEGER: &[StandardType] = &[
+            S8, U8, S16, U16, S32, U32, S64, U64, S8_2, U8_2, S16_2, U16_2, S32_2, U32_2, S64_2,
+            U64_2, S8_3, U8_3, S16_3, U16_3, S32_3, U32_3, S64_3, U64_3, S8_4, U8_4, S16_4, U16_4,
+            S32_4, U32_4, S64_4, U64_4, Vs8, Vu8, Vs16, Vu16,
+        ];
+        const FLOATING: &[StandardType] = &[
+            Float, Float2, Float3, Float4, Double, Double2, Double3, Double4,
+        ];
+        const TEXTUAL: &[StandardType] = &[String, Attribute];
+        const BOOLEAN: &[StandardType] = &[Boolean, Boolean2, Boolean3, Boolean4, Vb];
+
+        for (node_type, _) in NAMES {
+            let floating = FLOATING.contains(node_type);
+            let numeric = floating || INTEGER.contains(node_type);
+
+            assert_eq!(
+                node_type.is_textual(),
+                TEXTUAL.contains(node_type),
+                "{:?}",
+                node_type
+            );
+            assert_eq!(node_type.is_floating(), floating, "{:?}", node_type);
+            assert_eq!(node_type.is_numeric(), numeric, "{:?}", node_type);
+            assert_eq!(
+                node_type.is_boolean(),
+                BOOLEAN.contains(node_type),
+                "{:?}",
+                node_type
+            );
+        }
+    }
+}
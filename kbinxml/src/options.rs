@@ -1,16 +1,62 @@
+use crate::byte_buffer::DEFAULT_ALIGNMENT;
 use crate::compression_type::CompressionType;
 use crate::encoding_type::EncodingType;
+use crate::error::{KbinError, Result};
 
-#[derive(Clone, Debug, Default)]
+/// Order of the node and data buffers in a binary document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BufferLayout {
+    /// The node buffer followed by the data buffer, as written by Konami's tools
+    #[default]
+    Standard,
+
+    /// The data buffer followed by the node buffer. This is non-standard and only
+    /// used by a few third-party tools.
+    DataFirst,
+}
+
+#[derive(Clone, Debug)]
 pub struct Options {
     pub(crate) compression: CompressionType,
     pub(crate) encoding: EncodingType,
+    pub(crate) uppercase_hex: bool,
+    pub(crate) duplicate_attribute_error: bool,
+    pub(crate) alignment: u64,
+    pub(crate) strict: bool,
+    pub(crate) always_declaration: bool,
+    pub(crate) lenient_unknown_types: bool,
+    pub(crate) saturate_integers: bool,
+    pub(crate) infer_types: bool,
+    pub(crate) max_document_size: Option<usize>,
+    pub(crate) buffer_layout: BufferLayout,
+    pub(crate) shortest_floats: bool,
+    pub(crate) boolean_words: bool,
 }
 
 #[derive(Default)]
 pub struct OptionsBuilder {
-    compression: CompressionType,
-    encoding: EncodingType,
+    options: Options,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            compression: CompressionType::default(),
+            encoding: EncodingType::default(),
+            uppercase_hex: false,
+            duplicate_attribute_error: false,
+            alignment: DEFAULT_ALIGNMENT,
+            strict: false,
+            always_declaration: false,
+            lenient_unknown_types: false,
+            saturate_integers: false,
+            infer_types: false,
+            max_document_size: None,
+            buffer_layout: BufferLayout::Standard,
+            shortest_floats: false,
+            boolean_words: false,
+        }
+    }
 }
 
 impl Options {
@@ -18,6 +64,7 @@ impl Options {
         Self {
             compression,
             encoding,
+            ..Default::default()
         }
     }
 
@@ -31,23 +78,201 @@ impl Options {
             ..Default::default()
         }
     }
+
+    /// Compressed keys with `SHIFT_JIS` strings, as used by the files and network
+    /// payloads of Konami's arcade games. This is the same as `Options::default`.
+    pub fn konami_default() -> Self {
+        Self::new(CompressionType::Compressed, EncodingType::SHIFT_JIS)
+    }
+
+    /// Uncompressed keys with `UTF-8` strings, for documents whose keys are not
+    /// representable in sixbit, such as those produced by some newer games and
+    /// third-party tools.
+    pub fn utf8_uncompressed() -> Self {
+        Self::new(CompressionType::Uncompressed, EncodingType::UTF_8)
+    }
 }
 
 impl OptionsBuilder {
     pub fn compression(&mut self, compression: CompressionType) -> &mut Self {
-        self.compression = compression;
+        self.options.compression = compression;
         self
     }
 
     pub fn encoding(&mut self, encoding: EncodingType) -> &mut Self {
-        self.encoding = encoding;
+        self.options.encoding = encoding;
+        self
+    }
+
+    /// Set the compression from its name, `compressed` or `uncompressed`,
+    /// ignoring case.
+    pub fn compression_from_str(&mut self, name: &str) -> Result<&mut Self> {
+        self.options.compression = if name.eq_ignore_ascii_case("compressed") {
+            CompressionType::Compressed
+        } else if name.eq_ignore_ascii_case("uncompressed") {
+            CompressionType::Uncompressed
+        } else {
+            return Err(KbinError::UnknownCompressionName {
+                name: name.to_owned(),
+            });
+        };
+        Ok(self)
+    }
+
+    /// Set the encoding from its name, as accepted by `EncodingType::from_name`.
+    pub fn encoding_by_name(&mut self, name: &str) -> Result<&mut Self> {
+        self.options.encoding = EncodingType::from_name(name)?;
+        Ok(self)
+    }
+
+    /// Write `Binary` values as uppercase hexadecimal when converting to text
+    /// XML. Defaults to lowercase.
+    pub fn uppercase_hex(&mut self, uppercase_hex: bool) -> &mut Self {
+        self.options.uppercase_hex = uppercase_hex;
+        self
+    }
+
+    /// Return an error when a text XML element has the same attribute more than
+    /// once. By default, the last value wins and the attribute keeps the
+    /// position of its first occurrence.
+    pub fn duplicate_attribute_error(&mut self, duplicate_attribute_error: bool) -> &mut Self {
+        self.options.duplicate_attribute_error = duplicate_attribute_error;
+        self
+    }
+
+    /// Data buffer alignment in bytes, must be a power of two. Defaults to 4.
+    ///
+    /// Standard kbin files always use 4 byte alignment, so any other value
+    /// produces and reads files that are incompatible with standard kbin.
+    pub fn alignment(&mut self, alignment: u64) -> &mut Self {
+        self.options.alignment = alignment;
+        self
+    }
+
+    /// Enable extra consistency checks when writing, such as requiring a
+    /// `__size` attribute to match the length of the node's `Binary` value.
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Always write an XML declaration when converting to text XML, using UTF-8
+    /// for encodings that have no name of their own. By default, the declaration
+    /// is left out for those encodings.
+    pub fn always_declaration(&mut self, always_declaration: bool) -> &mut Self {
+        self.options.always_declaration = always_declaration;
+        self
+    }
+
+    /// Read text XML nodes with an unrecognized `__type` as `String` nodes holding
+    /// the raw text instead of returning an error. The original type name is kept
+    /// in a `__unknown_type` attribute.
+    pub fn lenient_unknown_types(&mut self, lenient_unknown_types: bool) -> &mut Self {
+        self.options.lenient_unknown_types = lenient_unknown_types;
+        self
+    }
+
+    /// Clamp out-of-range integer literals in text XML to the minimum or maximum
+    /// of the node type instead of returning an error. This applies to scalar,
+    /// tuple and array integer nodes.
+    pub fn saturate_integers(&mut self, saturate_integers: bool) -> &mut Self {
+        self.options.saturate_integers = saturate_integers;
+        self
+    }
+
+    /// Guess the type of text XML nodes that have no `__type` attribute from
+    /// their text, reading integers as `s32` and dotted quads as `ip4`. This is a
+    /// heuristic and may misclassify values, so it is off by default. An explicit
+    /// `__type` always takes precedence.
+    pub fn infer_types(&mut self, infer_types: bool) -> &mut Self {
+        self.options.infer_types = infer_types;
+        self
+    }
+
+    /// Maximum size in bytes of a binary document, as declared by the node and
+    /// data buffer lengths in its header. Documents declaring more are rejected
+    /// before anything is read. The declared size is always limited to the length
+    /// of the input, which is the default.
+    pub fn max_document_size(&mut self, max_document_size: usize) -> &mut Self {
+        self.options.max_document_size = Some(max_document_size);
+        self
+    }
+
+    /// Order of the node and data buffers in binary output. When reading with the
+    /// default `BufferLayout::Standard`, documents that have the node buffer
+    /// second are detected and read as `BufferLayout::DataFirst`.
+    pub fn buffer_layout(&mut self, buffer_layout: BufferLayout) -> &mut Self {
+        self.options.buffer_layout = buffer_layout;
+        self
+    }
+
+    /// Write `float` and `double` values in text XML in the shortest form that
+    /// reads back to the same value, such as `1e-20` or `-0.0`. By default, they
+    /// are written with 6 decimal places, which loses very small values, the
+    /// sign of zero and digits beyond the sixth.
+    pub fn shortest_floats(&mut self, shortest_floats: bool) -> &mut Self {
+        self.options.shortest_floats = shortest_floats;
+        self
+    }
+
+    /// Write `bool` values and arrays in text XML as `true` and `false` instead
+    /// of `1` and `0`. Konami's tools only write digits, so this is off by
+    /// default. Both forms are always accepted when reading.
+    pub fn boolean_words(&mut self, boolean_words: bool) -> &mut Self {
+        self.options.boolean_words = boolean_words;
         self
     }
 
     pub fn build(&self) -> Options {
-        Options {
-            compression: self.compression,
-            encoding: self.encoding,
+        self.options.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        let options = Options::konami_default();
+        let default = Options::default();
+        assert_eq!(options.compression, default.compression);
+        assert_eq!(options.encoding, default.encoding);
+
+        let options = Options::utf8_uncompressed();
+        assert_eq!(options.compression, CompressionType::Uncompressed);
+        assert_eq!(options.encoding, EncodingType::UTF_8);
+    }
+
+    #[test]
+    fn by_name() {
+        let options = Options::builder()
+            .encoding_by_name("shift_jis")
+            .unwrap()
+            .compression_from_str("Uncompressed")
+            .unwrap()
+            .build();
+        assert_eq!(options.encoding, EncodingType::SHIFT_JIS);
+        assert_eq!(options.compression, CompressionType::Uncompressed);
+
+        for (name, encoding) in [
+            ("none", EncodingType::None),
+            ("ASCII", EncodingType::ASCII),
+            ("latin1", EncodingType::ISO_8859_1),
+            ("euc-jp", EncodingType::EUC_JP),
+            ("utf8", EncodingType::UTF_8),
+        ] {
+            assert_eq!(EncodingType::from_name(name).unwrap(), encoding, "{}", name);
         }
+
+        let mut builder = Options::builder();
+        assert!(matches!(
+            builder.encoding_by_name("klingon"),
+            Err(KbinError::Encoding { .. })
+        ));
+        assert!(matches!(
+            builder.compression_from_str("zip"),
+            Err(KbinError::UnknownCompressionName { .. })
+        ));
     }
 }
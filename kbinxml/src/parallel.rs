@@ -0,0 +1,229 @@
+//! Parallel encoding of independent subtrees.
+//!
+//! The node buffer is a plain byte stream, so the node buffers of separately
+//! encoded subtrees can simply be concatenated. The data buffer is harder: 1 and 2
+//! byte values are packed into shared 4 byte slots by `write_aligned`, and the
+//! open slot for each size is carried from one node to the next. A subtree encoded
+//! on its own always starts with no open slots.
+//!
+//! Each top-level child of the root is encoded into its own buffer pair on the
+//! `rayon` thread pool, starting from an empty, 4 byte aligned buffer. The buffers
+//! are then merged in document order. Because the data buffer position is always
+//! 4 byte aligned between values, a separately encoded subtree produces the same
+//! bytes as the sequential path unless the merged buffer has a partially filled
+//! slot of a size the subtree also wrote. In that case the sequential encoder
+//! would have packed the first value into the open slot, so the subtree is
+//! re-encoded directly into the merged buffer instead. Otherwise the subtree's
+//! slot offsets are rebased onto the merged buffer's position and its bytes are
+//! appended. Either way the output is byte-identical to `Writer::to_binary`.
+//!
+//! Documents where most subtrees leave a slot open that the next subtree writes
+//! into, such as siblings each holding a single `s16`, fall back to sequential
+//! encoding for most subtrees and are slower than `Writer::to_binary`.
+
+use rayon::prelude::*;
+use snafu::ResultExt;
+
+use crate::byte_buffer::ByteBufferWrite;
+use crate::node::{Node, NodeCollection};
+use crate::node_types::StandardType;
+use crate::options::Options;
+use crate::writer::{
+    write_collection_start, write_node_end, write_node_start, DataBufferSnafu, NodeBufferSnafu,
+    Writeable, WriterError,
+};
+
+pub trait ParallelWriteable: Writeable {
+    type Child: Writeable + Sync;
+
+    /// Write everything but the children and the closing `NodeEnd`.
+    fn write_start(
+        &self,
+        options: &Options,
+        node_buf: &mut ByteBufferWrite,
+        data_buf: &mut ByteBufferWrite,
+    ) -> Result<(), WriterError>;
+
+    /// The subtrees that are encoded concurrently, in document order.
+    fn subtrees(&self) -> Vec<&Self::Child>;
+}
+
+impl ParallelWriteable for NodeCollection {
+    type Child = NodeCollection;
+
+    fn write_start(
+        &self,
+        options: &Options,
+        node_buf: &mut ByteBufferWrite,
+        data_buf: &mut ByteBufferWrite,
+    ) -> Result<(), WriterError> {
+        write_collection_start(self, options, node_buf, data_buf, &mut None)
+    }
+
+    fn subtrees(&self) -> Vec<&Self::Child> {
+        self.children().iter().collect()
+    }
+}
+
+impl ParallelWriteable for Node {
+    type Child = Node;
+
+    fn write_start(
+        &self,
+        options: &Options,
+        node_buf: &mut ByteBufferWrite,
+        data_buf: &mut ByteBufferWrite,
+    ) -> Result<(), WriterError> {
+        write_node_start(self, options, node_buf, data_buf, &mut None)
+    }
+
+    fn subtrees(&self) -> Vec<&Self::Child> {
+        self.children().iter().collect()
+    }
+}
+
+pub(crate) fn write_node_parallel<T>(
+    input: &T,
+    options: &Options,
+    node_buf: &mut ByteBufferWrite,
+    data_buf: &mut ByteBufferWrite,
+) -> Result<(), WriterError>
+where
+    T: ParallelWriteable,
+{
+    input.write_start(options, node_buf, data_buf)?;
+
+    let subtrees = input.subtrees();
+    let encoded = subtrees
+        .par_iter()
+        .map(|child| {
+            let mut child_node_buf = ByteBufferWrite::new(Vec::new());
+            let mut child_data_buf = ByteBufferWrite::with_alignment(Vec::new(), options.alignment);
+            child.write_node(options, &mut child_node_buf, &mut child_data_buf)?;
+
+            Ok((child_node_buf, child_data_buf))
+        })
+        .collect::<Result<Vec<_>, WriterError>>()?;
+
+    let node_type = StandardType::NodeStart;
+    for (child, (child_node_buf, child_data_buf)) in subtrees.into_iter().zip(encoded) {
+        if data_buf.can_append(&child_data_buf) {
+            trace!("write_node_parallel => appending encoded subtree");
+
+            node_buf
+                .append_raw(child_node_buf)
+                .context(NodeBufferSnafu { node_type })?;
+            data_buf
+                .append(child_data_buf)
+                .context(DataBufferSnafu { node_type })?;
+        } else {
+            trace!("write_node_parallel => re-encoding subtree sequentially");
+
+            child.write_node(options, node_buf, data_buf)?;
+        }
+    }
+
+    write_node_end(node_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use super::*;
+
+    use test::Bencher;
+
+    use crate::value::Value;
+    use crate::writer::Writer;
+
+    fn sample(children: usize) -> Node {
+        let mut root = Node::new("root");
+        for i in 0..children {
+            let mut child = Node::new("entry");
+            child.set_attr("id", i.to_string());
+            child.append_child(Node::with_value("flag", Value::Boolean(i % 3 == 0)));
+            if i % 2 == 0 {
+                child.append_child(Node::with_value("small", Value::U8(i as u8)));
+            }
+            child.append_child(Node::with_value("short", Value::S16(i as i16)));
+            child.append_child(Node::with_value("name", Value::String(format!("n{}", i))));
+            child.append_child(Node::with_value("num", Value::U32(i as u32)));
+            root.append_child(child);
+        }
+
+        // Leave a 1 byte slot open before the first child
+        root.set_value(Some(Value::U8(7)));
+        root
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        for &children in &[0, 1, 2, 5, 64] {
+            let node = sample(children);
+            let sequential = Writer::new().to_binary(&node).unwrap();
+            let parallel = Writer::new().to_binary_parallel(&node).unwrap();
+            assert_eq!(sequential, parallel, "children: {}", children);
+
+            let (collection, _) = crate::from_slice(&sequential).unwrap();
+            let parallel = Writer::new().to_binary_parallel(&collection).unwrap();
+            assert_eq!(sequential, parallel, "children: {}", children);
+        }
+    }
+
+    #[test]
+    fn parallel_appends_subtrees() {
+        let mut root = Node::new("root");
+        for i in 0..4 {
+            root.append_child(Node::with_value("num", Value::U32(i)));
+        }
+        let mut entry = Node::with_value("entry", Value::String("abc".into()));
+        entry.append_child(Node::with_value("small", Value::U8(1)));
+        root.append_child(entry);
+        root.append_child(Node::with_value("small", Value::U8(2)));
+        root.append_child(Node::with_value("num", Value::U32(4)));
+
+        let sequential = Writer::new().to_binary(&root).unwrap();
+        let parallel = Writer::new().to_binary_parallel(&root).unwrap();
+        assert_eq!(sequential, parallel);
+    }
+
+    #[test]
+    fn parallel_observer() {
+        use std::sync::{Arc, Mutex};
+
+        let node = sample(5);
+        let observe = |parallel: bool| {
+            let index = Arc::new(Mutex::new(Vec::new()));
+            let mut writer = Writer::new();
+            writer.set_observer({
+                let index = Arc::clone(&index);
+                move |key, offset| index.lock().unwrap().push((key.to_owned(), offset))
+            });
+            let output = if parallel {
+                writer.to_binary_parallel(&node).unwrap()
+            } else {
+                writer.to_binary(&node).unwrap()
+            };
+            let index = index.lock().unwrap().clone();
+
+            (output, index)
+        };
+
+        let (sequential, index) = observe(false);
+        assert_eq!(index.len(), 29);
+        assert_eq!(observe(true), (sequential, index));
+    }
+
+    #[bench]
+    fn bench_sequential(b: &mut Bencher) {
+        let node = sample(4096);
+        b.iter(|| Writer::new().to_binary(&node).unwrap());
+    }
+
+    #[bench]
+    fn bench_parallel(b: &mut Bencher) {
+        let node = sample(4096);
+        b.iter(|| Writer::new().to_binary_parallel(&node).unwrap());
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Write;
+
 use bytes::Bytes;
 
 use crate::error::Result;
@@ -5,11 +7,43 @@ use crate::node::NodeCollection;
 use crate::node_types::StandardType;
 use crate::reader::Reader;
 
-pub struct Printer;
+/// Debug printer for binary XML documents and parsed collections.
+///
+/// Create one with `Printer::new()` and limit how deep the output goes with
+/// `max_depth`. By default, everything is printed.
+#[derive(Clone, Debug, Default)]
+pub struct Printer {
+    max_depth: Option<usize>,
+}
 
 impl Printer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only print the top `max_depth` levels of a collection, with the root at
+    /// the first level. Deeper content is replaced with a `…` marker. By default,
+    /// there is no limit.
+    pub fn max_depth(&mut self, max_depth: usize) -> &mut Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Dump `input` with the default settings, see `dump`.
     pub fn run(input: impl Into<Bytes>) -> Result<()> {
-        let mut reader = Reader::new(input.into())?;
+        Self::new().dump(input)
+    }
+
+    /// Print every node definition in `input` followed by the parsed collection,
+    /// up to the depth limit.
+    pub fn dump(&self, input: impl Into<Bytes>) -> Result<()> {
+        eprint!("{}", self.format_dump(input.into())?);
+
+        Ok(())
+    }
+
+    fn format_dump(&self, input: Bytes) -> Result<String> {
+        let mut reader = Reader::new(input)?;
         let mut nodes = Vec::new();
         let mut definitions = Vec::new();
 
@@ -32,9 +66,32 @@ impl Printer {
             }
         }
 
-        let mut indent = 0;
+        let mut output = String::new();
+        let mut depth = 0usize;
+        let mut elided = false;
         for (node_type, is_array, identifier) in nodes {
-            eprint!(
+            // Attributes and node ends are printed inside their node
+            let indent = depth * 2;
+            match node_type {
+                StandardType::Attribute | StandardType::FileEnd => {},
+                StandardType::NodeEnd => depth = depth.saturating_sub(1),
+                _ => depth += 1,
+            };
+
+            // Definitions past the depth limit are replaced with a single marker
+            if let Some(max_depth) = self.max_depth {
+                if indent >= max_depth * 2 && node_type != StandardType::FileEnd {
+                    if !elided {
+                        let _ = writeln!(output, "{:indent$} - …", "", indent = max_depth * 2);
+                        elided = true;
+                    }
+                    continue;
+                }
+            }
+            elided = false;
+
+            let _ = write!(
+                output,
                 "{:indent$} - {:?} (is_array: {}",
                 "",
                 node_type,
@@ -42,24 +99,237 @@ impl Printer {
                 indent = indent
             );
             if let Some(identifier) = identifier {
-                eprint!(", identifier: {}", identifier);
+                let _ = write!(output, ", identifier: {}", identifier);
             }
-            eprintln!(")");
-
-            match node_type {
-                StandardType::Attribute => {},
-                StandardType::NodeEnd => indent -= 2,
-                _ => indent += 2,
-            };
+            output.push_str(")\n");
         }
 
         let collection: Option<NodeCollection> = definitions.into_iter().collect();
 
         match collection {
-            Some(collection) => eprintln!("collection: {:#}", collection),
-            None => eprintln!("collection: {:?}", collection),
+            Some(collection) if self.max_depth.is_some() => {
+                output.push_str("collection:\n");
+                output.push_str(&self.format_filtered(&collection, &|_| true));
+            },
+            Some(collection) => {
+                let _ = writeln!(output, "collection: {:#}", collection);
+            },
+            None => {
+                let _ = writeln!(output, "collection: {:?}", collection);
+            },
         };
 
-        Ok(())
+        Ok(output)
+    }
+
+    /// Print the keys, types and values of `collection` and its descendants, up
+    /// to the depth limit.
+    pub fn print(&self, collection: &NodeCollection) {
+        self.print_filtered(collection, |_| true);
+    }
+
+    /// Print the subtrees of `collection` for which `predicate` matches the root
+    /// of the subtree or one of its ancestors. The ancestors of matching subtrees
+    /// are printed to show where they are, and runs of other siblings are elided
+    /// with a `…` marker.
+    pub fn print_filtered<F>(&self, collection: &NodeCollection, predicate: F)
+    where
+        F: Fn(&NodeCollection) -> bool,
+    {
+        eprint!("{}", self.format_filtered(collection, &predicate));
+    }
+
+    fn format_filtered<F>(&self, collection: &NodeCollection, predicate: &F) -> String
+    where
+        F: Fn(&NodeCollection) -> bool,
+    {
+        let mut output = String::new();
+        if self.max_depth == Some(0) {
+            output.push_str(" - …\n");
+        } else {
+            self.write_filtered(&mut output, collection, predicate, 0, false);
+        }
+
+        output
+    }
+
+    fn write_filtered<F>(
+        &self,
+        output: &mut String,
+        collection: &NodeCollection,
+        predicate: &F,
+        depth: usize,
+        matched: bool,
+    ) where
+        F: Fn(&NodeCollection) -> bool,
+    {
+        let matched = matched || predicate(collection);
+        let base = collection.base();
+        let key = match base.key() {
+            Ok(Some(key)) => key,
+            _ => String::from("?"),
+        };
+        let indent = depth * 2;
+
+        let _ = write!(
+            output,
+            "{:indent$} - {} ({})",
+            "",
+            key,
+            base.node_type,
+            indent = indent
+        );
+        if matched && base.node_type != StandardType::NodeStart {
+            if let Ok(value) = base.value() {
+                let _ = write!(output, ": {}", value.to_string_truncated(16));
+            }
+        }
+        output.push('\n');
+
+        // Children past the depth limit are replaced with a single marker
+        if self
+            .max_depth
+            .is_some_and(|max_depth| depth + 1 >= max_depth)
+        {
+            if !collection.children().is_empty() {
+                let _ = writeln!(output, "{:indent$} - …", "", indent = indent + 2);
+            }
+            return;
+        }
+
+        let mut elided = false;
+        for child in collection.children() {
+            if matched || Self::has_match(child, predicate) {
+                self.write_filtered(output, child, predicate, depth + 1, matched);
+                elided = false;
+            } else if !elided {
+                let _ = writeln!(output, "{:indent$} - …", "", indent = indent + 2);
+                elided = true;
+            }
+        }
+    }
+
+    fn has_match<F>(collection: &NodeCollection, predicate: &F) -> bool
+    where
+        F: Fn(&NodeCollection) -> bool,
+    {
+        predicate(collection) ||
+            collection
+                .children()
+                .iter()
+                .any(|child| Self::has_match(child, predicate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::node::Node;
+
+    #[test]
+    fn format_filtered() {
+        let input = br#"
+            <root>
+                <a><x __type="u8">1</x></a>
+                <b __type="u8">2</b>
+                <player><name>abc</name><score __type="s32">5</score></player>
+                <c/>
+                <d/>
+            </root>
+        "#;
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+
+        let output = Printer::new().format_filtered(&collection, &|node: &NodeCollection| {
+            node.base().key().ok().flatten().as_deref() == Some("player")
+        });
+
+        let expected = [
+            " - root (void)",
+            "   - …",
+            "   - player (void)",
+            "     - name (str): abc",
+            "     - score (s32): 5",
+            "   - …",
+        ];
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn max_depth() {
+        let input = br#"
+            <root>
+                <a><x><y __type="u8">1</y></x></a>
+                <b __type="u8">2</b>
+            </root>
+        "#;
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+        let all = |_: &NodeCollection| true;
+
+        let output = Printer::new()
+            .max_depth(2)
+            .format_filtered(&collection, &all);
+        let expected = [
+            " - root (void)",
+            "   - a (void)",
+            "     - …",
+            "   - b (u8): 2",
+        ];
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+
+        let output = Printer::new()
+            .max_depth(1)
+            .format_filtered(&collection, &all);
+        assert_eq!(output.lines().collect::<Vec<_>>(), [
+            " - root (void)",
+            "   - …"
+        ]);
+
+        let output = Printer::new().format_filtered(&collection, &all);
+        assert_eq!(output.lines().count(), 5);
+    }
+
+    #[test]
+    fn format_dump() {
+        let input = br#"<root id="1"><a><b __type="u8">1</b></a></root>"#;
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+        let data = Bytes::from(collection.to_binary().unwrap());
+
+        let output = Printer::new()
+            .max_depth(2)
+            .format_dump(data.clone())
+            .unwrap();
+        let expected = [
+            " - NodeStart (is_array: false, identifier: root)",
+            "   - Attribute (is_array: false, identifier: id)",
+            "   - NodeStart (is_array: false, identifier: a)",
+            "     - …",
+            "   - NodeEnd (is_array: true)",
+            " - FileEnd (is_array: true)",
+            "collection:",
+            " - root (void)",
+            "   - a (void)",
+            "     - …",
+        ];
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+
+        let output = Printer::new().format_dump(data).unwrap();
+        assert!(output.contains("identifier: b"));
+    }
+
+    #[test]
+    fn format_dump_extra_node_end() {
+        let mut data = Node::new("a").to_binary().unwrap();
+        assert_eq!(&data[11..13], &[0xfe, 0xff]);
+        data[12] = 0xfe;
+        data[13] = 0xff;
+
+        let output = Printer::new().format_dump(Bytes::from(data)).unwrap();
+        assert_eq!(output.lines().take(4).collect::<Vec<_>>(), [
+            " - NodeStart (is_array: false, identifier: a)",
+            "   - NodeEnd (is_array: true)",
+            " - NodeEnd (is_array: true)",
+            " - FileEnd (is_array: true)",
+        ]);
     }
 }
@@ -1,17 +1,28 @@
+use std::cmp;
 use std::io::{self, Cursor, Seek, SeekFrom};
 
 use byteorder::{BigEndian, ReadBytesExt};
 use bytes::Bytes;
 use snafu::{ResultExt, Snafu};
 
-use crate::byte_buffer::{ByteBufferError, ByteBufferRead};
+use crate::byte_buffer::{strip_trailing_null_bytes, ByteBufferError, ByteBufferRead};
 use crate::compression_type::{CompressionType, UnknownCompression};
 use crate::encoding_type::{EncodingError, EncodingType};
+use crate::error::KbinError;
 use crate::node::{Key, NodeData, NodeDefinition};
 use crate::node_types::{StandardType, UnknownKbinType};
+use crate::options::{BufferLayout, Options};
 use crate::sixbit::{Sixbit, SixbitError};
+use crate::value::Value;
 use crate::{ARRAY_MASK, SIGNATURE};
 
+/// Whether `buf` looks like a node buffer, ending in `FileEnd` and padding.
+fn ends_with_file_end(buf: &[u8]) -> bool {
+    const FILE_END: u8 = StandardType::FileEnd as u8 | ARRAY_MASK;
+
+    buf.iter().rev().find(|&&b| b != 0) == Some(&FILE_END)
+}
+
 #[derive(Debug, Snafu)]
 pub enum ReaderError {
     #[snafu(display("Failed to read signature from header"))]
@@ -50,6 +61,16 @@ pub enum ReaderError {
     ))]
     DataLengthSeek { len_node: u32, source: io::Error },
 
+    #[snafu(display("Invalid data buffer alignment: {}", alignment))]
+    InvalidAlignment { alignment: u64 },
+
+    #[snafu(display(
+        "Declared document size {} exceeds the limit of {} bytes",
+        declared,
+        limit
+    ))]
+    SizeLimitExceeded { declared: u64, limit: u64 },
+
     #[snafu(display("Attempted to read past the end of the node buffer"))]
     EndOfNodeBuffer,
 
@@ -82,6 +103,56 @@ pub enum ReaderError {
         node_type: StandardType,
         source: ByteBufferError,
     },
+
+    #[snafu(display("Expected the root node, found node type {}", node_type))]
+    InvalidRootNode { node_type: StandardType },
+
+    #[snafu(display("Failed to decode the root node key"))]
+    RootKey {
+        #[snafu(source(from(crate::KbinError, Box::new)))]
+        source: Box<crate::KbinError>,
+    },
+
+    #[snafu(display("Attribute read outside of a node (node buffer offset: {})", offset))]
+    AttributeOutsideNode { offset: u64 },
+
+    #[snafu(display("Unbalanced node end read (node buffer offset: {})", offset))]
+    UnbalancedNodeEnd { offset: u64 },
+
+    #[snafu(display("Reached file end with {} node(s) still open", depth))]
+    UnclosedNodes { depth: usize },
+
+    #[snafu(display(
+        "Data buffer not fully consumed (consumed: {}, length: {})",
+        consumed,
+        len
+    ))]
+    DataBufferNotConsumed { consumed: u64, len: u64 },
+}
+
+/// Counts gathered by `Reader::validate`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStats {
+    /// Number of nodes, not counting attributes
+    pub nodes: usize,
+
+    /// Number of attributes
+    pub attributes: usize,
+
+    /// Number of input bytes used by the header, node buffer and data buffer
+    pub bytes_consumed: usize,
+}
+
+/// A recoverable problem in a binary XML document, as collected by
+/// `from_binary_with_report`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// The string value of the node or attribute `key` has bytes that are
+    /// invalid in the document's encoding, which are replaced when decoded
+    CharacterReplaced { key: String },
+
+    /// The input has `bytes` bytes after the end of the document
+    TrailingData { bytes: usize },
 }
 
 pub struct Reader {
@@ -91,11 +162,39 @@ pub struct Reader {
     pub(crate) node_buf: ByteBufferRead,
     pub(crate) data_buf: ByteBufferRead,
 
-    data_buf_start: u64,
+    data_buf_len: u64,
+    trailing: Bytes,
+    document_end: usize,
+
+    reached_end: bool,
+    last_error: Option<ReaderError>,
 }
 
 impl Reader {
     pub fn new(input: Bytes) -> Result<Self, ReaderError> {
+        Self::with_options(input, &Options::default())
+    }
+
+    /// Create a reader using the data buffer alignment from `options`.
+    pub fn with_options(input: Bytes, options: &Options) -> Result<Self, ReaderError> {
+        let alignment = options.alignment;
+        if !alignment.is_power_of_two() {
+            return Err(ReaderError::InvalidAlignment { alignment });
+        }
+
+        // The declared lengths may never exceed the input, even with a larger limit
+        let limit = match options.max_document_size {
+            Some(max) => cmp::min(max, input.len()),
+            None => input.len(),
+        } as u64;
+        let check_size = |declared: u64| {
+            if declared > limit {
+                Err(ReaderError::SizeLimitExceeded { declared, limit })
+            } else {
+                Ok(())
+            }
+        };
+
         let mut header = Cursor::new(&input);
 
         let signature = header.read_u8().context(SignatureSnafu)?;
@@ -119,40 +218,139 @@ impl Reader {
             signature, compress_byte, compression, encoding_byte, encoding
         );
 
-        let len_node = header
+        // With the standard layout, the node buffer comes first and the data buffer
+        // second. `BufferLayout::DataFirst` swaps them.
+        let len_first = header
             .read_u32::<BigEndian>()
             .context(NodeBufferLengthSnafu)?;
-        info!("len_node: {0} (0x{0:x})", len_node);
+        info!("len_first: {0} (0x{0:x})", len_first);
+        check_size(8 + len_first as u64 + 4)?;
 
-        // The length of the data buffer is the 4 bytes right after the node buffer.
+        // The length of the second buffer is the 4 bytes right after the first.
         header
-            .seek(SeekFrom::Current(len_node as i64))
-            .context(DataLengthSeekSnafu { len_node })?;
+            .seek(SeekFrom::Current(len_first as i64))
+            .context(DataLengthSeekSnafu {
+                len_node: len_first,
+            })?;
 
-        let len_data = header
+        let len_second = header
             .read_u32::<BigEndian>()
             .context(DataBufferLengthSnafu)?;
-        info!("len_data: {0} (0x{0:x})", len_data);
+        info!("len_second: {0} (0x{0:x})", len_second);
+        check_size(8 + len_first as u64 + 4 + len_second as u64)?;
+
+        // We have read 8 bytes so far, so the first buffer starts there. After that
+        // is the length of the second buffer, then the second buffer itself.
+        let first = 8..8 + len_first as usize;
+        let second_start = first.end + 4;
+        let second = second_start..second_start + len_second as usize;
+
+        let layout = match options.buffer_layout {
+            BufferLayout::Standard
+                if !ends_with_file_end(&input[first.clone()]) &&
+                    ends_with_file_end(&input[second.clone()]) =>
+            {
+                info!("node buffer found after the data buffer, using data first layout");
+                BufferLayout::DataFirst
+            },
+            layout => layout,
+        };
 
-        // We have read 8 bytes so far, so offset the start of the node buffer from
-        // the start of the input data. After that is the length of the data buffer.
-        // The data buffer is everything after that.
-        let node_buffer_end = 8 + len_node as usize;
-        let data_buffer_start = node_buffer_end + 4;
-        let node_buf = ByteBufferRead::new(input.slice(8..node_buffer_end));
-        let data_buf = ByteBufferRead::new(input.slice(data_buffer_start..));
+        // The standard data buffer slice includes any trailing bytes
+        let (node_buf, data_buf, data_buf_len) = match layout {
+            BufferLayout::Standard => (first, second_start..input.len(), len_second),
+            BufferLayout::DataFirst => (second, first, len_first),
+        };
+        let document_end = second_start + len_second as usize;
 
         Ok(Self {
             compression,
             encoding,
 
-            node_buf,
-            data_buf,
+            node_buf: ByteBufferRead::new(input.slice(node_buf)),
+            data_buf: ByteBufferRead::with_alignment(input.slice(data_buf), alignment),
 
-            data_buf_start: data_buffer_start as u64,
+            data_buf_len: data_buf_len as u64,
+            trailing: input.slice(document_end..),
+            document_end,
+
+            reached_end: false,
+            last_error: None,
         })
     }
 
+    /// Read the key and type of the root node of `input`, such as to route a file
+    /// by its root element, without reading the rest of the document.
+    ///
+    /// Returns `ReaderError::InvalidRootNode` if the first node is not a
+    /// `NodeStart`, including roots that hold a value.
+    pub fn read_root_info(input: impl Into<Bytes>) -> Result<(String, StandardType), ReaderError> {
+        let mut reader = Self::new(input.into())?;
+        let definition = reader.read_node_definition()?;
+
+        let node_type = definition.node_type;
+        if node_type != StandardType::NodeStart {
+            return Err(ReaderError::InvalidRootNode { node_type });
+        }
+
+        let key = definition
+            .key()
+            .context(RootKeySnafu)?
+            .ok_or(ReaderError::InvalidRootNode { node_type })?;
+
+        Ok((key, node_type))
+    }
+
+    /// Walk every node definition in `input` without building a tree, checking
+    /// that nodes are balanced, `FileEnd` is reached and the data buffer is fully
+    /// consumed.
+    pub fn validate(input: impl Into<Bytes>) -> Result<FileStats, ReaderError> {
+        let mut reader = Self::new(input.into())?;
+        let mut stats = FileStats::default();
+        let mut depth = 0usize;
+
+        loop {
+            let offset = reader.node_buf.position();
+            let definition = reader.read_node_definition()?;
+
+            match definition.node_type {
+                StandardType::Attribute => {
+                    if depth == 0 {
+                        return Err(ReaderError::AttributeOutsideNode { offset });
+                    }
+                    stats.attributes += 1;
+                },
+                StandardType::NodeEnd => {
+                    if depth == 0 {
+                        return Err(ReaderError::UnbalancedNodeEnd { offset });
+                    }
+                    depth -= 1;
+                },
+                StandardType::FileEnd => {
+                    if depth != 0 {
+                        return Err(ReaderError::UnclosedNodes { depth });
+                    }
+                    break;
+                },
+                _ => {
+                    stats.nodes += 1;
+                    depth += 1;
+                },
+            }
+        }
+
+        let consumed = reader.data_buf.position();
+        if consumed != reader.data_buf_len {
+            return Err(ReaderError::DataBufferNotConsumed {
+                consumed,
+                len: reader.data_buf_len,
+            });
+        }
+        stats.bytes_consumed = reader.document_end;
+
+        Ok(stats)
+    }
+
     fn parse_node_type(raw_node_type: u8) -> Result<(StandardType, bool), ReaderError> {
         let is_array = raw_node_type & ARRAY_MASK == ARRAY_MASK;
         let node_type = raw_node_type & !ARRAY_MASK;
@@ -171,8 +369,34 @@ impl Reader {
         self.encoding
     }
 
+    /// Length of the node buffer slice, as given by the header.
+    #[inline]
+    pub fn node_buffer_len(&self) -> usize {
+        self.node_buf.get_ref().len()
+    }
+
+    /// Length of the data buffer, as given by the header. Any bytes in the input
+    /// after it are available from `trailing_bytes`.
+    #[inline]
+    pub fn data_buffer_len(&self) -> usize {
+        self.data_buf_len as usize
+    }
+
+    /// The error that ended iteration over this reader, if any. Iteration that
+    /// stops after the `FileEnd` definition leaves this as `None`.
+    pub fn last_error(&self) -> Option<&ReaderError> {
+        self.last_error.as_ref()
+    }
+
+    /// Bytes in the input after the data buffer's declared length, such as
+    /// metadata appended by a container format. Empty if the input ends with the
+    /// data buffer or is shorter than the declared length.
+    pub fn trailing_bytes(&self) -> &[u8] {
+        &self.trailing
+    }
+
     pub fn check_if_node_buffer_end(&self) -> Result<(), ReaderError> {
-        if self.node_buf.position() >= self.data_buf_start {
+        if self.node_buf.position() >= self.node_buf.get_ref().len() as u64 {
             Err(ReaderError::EndOfNodeBuffer)
         } else {
             Ok(())
@@ -293,18 +517,363 @@ impl Reader {
     pub fn read_bytes(&mut self) -> Result<Bytes, ByteBufferError> {
         self.data_buf.buf_read()
     }
+
+    /// Read a value of `node_type` at `offset` in the data buffer, such as an
+    /// offset recorded by a `NodeObserver` while writing. The data buffer position
+    /// is restored afterwards, so reading node definitions is unaffected.
+    ///
+    /// Offsets are relative to the start of the data buffer, after its length
+    /// field. The value is read like during iteration, so a 1 or 2 byte value is
+    /// read at `offset` itself even when it is packed into a shared slot.
+    pub fn read_value_at(
+        &mut self,
+        offset: u64,
+        node_type: StandardType,
+        is_array: bool,
+    ) -> Result<Value, KbinError> {
+        let saved = self.data_buf.clone();
+        let result = self.read_value_at_inner(offset, node_type, is_array);
+        self.data_buf = saved;
+
+        result
+    }
+
+    fn read_value_at_inner(
+        &mut self,
+        offset: u64,
+        node_type: StandardType,
+        is_array: bool,
+    ) -> Result<Value, KbinError> {
+        self.data_buf
+            .seek_to(offset as usize)
+            .context(DataBufferSnafu { node_type })?;
+        let data = self.read_node_data(node_type, is_array)?;
+
+        let value = match node_type {
+            StandardType::String | StandardType::Attribute => {
+                let text = self
+                    .encoding
+                    .decode_bytes(strip_trailing_null_bytes(&data))?;
+
+                if node_type == StandardType::String {
+                    Value::String(text)
+                } else {
+                    Value::Attribute(text)
+                }
+            },
+            node_type => Value::from_standard_type(node_type, is_array, &data)?
+                .ok_or(KbinError::InvalidNodeType { node_type })?,
+        };
+
+        Ok(value)
+    }
 }
 
 impl Iterator for Reader {
     type Item = NodeDefinition;
 
     fn next(&mut self) -> Option<NodeDefinition> {
+        if self.reached_end || self.last_error.is_some() {
+            return None;
+        }
+
         match self.read_node_definition() {
-            Ok(v) => Some(v),
+            Ok(v) => {
+                self.reached_end = v.node_type == StandardType::FileEnd;
+                Some(v)
+            },
             Err(e) => {
                 error!("Error reading node definition in `next()`: {}", e);
+                self.last_error = Some(e);
                 None
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use byteorder::ByteOrder;
+
+    use crate::node::{Node, NodeCollection};
+    use crate::writer::Writer;
+
+    fn sample() -> Vec<u8> {
+        let mut node = Node::with_attrs("root", &[("id", "1")]);
+        node.append_child(Node::with_value("a", Value::U8(1)));
+        node.append_child(Node::with_value("b", Value::S16(2)));
+        node.append_child(Node::with_value("c", Value::U8(3)));
+
+        node.to_binary().unwrap()
+    }
+
+    #[test]
+    fn size_limit() {
+        let data = sample();
+        let len = data.len() as u64;
+
+        let options = Options::builder().max_document_size(data.len() - 1).build();
+        match Reader::with_options(Bytes::from(data.clone()), &options) {
+            Err(ReaderError::SizeLimitExceeded { declared, limit }) => {
+                assert_eq!((declared, limit), (len, len - 1));
+            },
+            _ => panic!("expected size limit error"),
+        }
+
+        // A header claiming a huge data buffer is rejected against the input length
+        let mut huge = data.clone();
+        let len_node = BigEndian::read_u32(&huge[4..8]) as usize;
+        BigEndian::write_u32(&mut huge[8 + len_node..], u32::MAX);
+        assert!(matches!(
+            Reader::new(Bytes::from(huge)),
+            Err(ReaderError::SizeLimitExceeded { limit, .. }) if limit == len
+        ));
+
+        let options = Options::builder().max_document_size(usize::MAX).build();
+        assert!(Reader::with_options(Bytes::from(data), &options).is_ok());
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut data = sample();
+        let reader = Reader::new(Bytes::from(data.clone())).unwrap();
+        assert!(reader.trailing_bytes().is_empty());
+        let data_buffer_len = reader.data_buffer_len();
+
+        data.extend_from_slice(b"TRAILER");
+        let reader = Reader::new(Bytes::from(data)).unwrap();
+        assert_eq!(reader.trailing_bytes(), b"TRAILER");
+        assert_eq!(reader.data_buffer_len(), data_buffer_len);
+    }
+
+    #[test]
+    fn empty_data_buffer() {
+        let mut node = Node::new("root");
+        node.append_child(Node::new("a"));
+        node.append_child(Node::new("b"));
+
+        let data = node.to_binary().unwrap();
+        let len_node = BigEndian::read_u32(&data[4..8]) as usize;
+        assert_eq!(BigEndian::read_u32(&data[8 + len_node..]), 0);
+        assert_eq!(data.len(), 8 + len_node + 4);
+
+        let collection = Reader::new(Bytes::from(data.clone()))
+            .unwrap()
+            .collect::<Option<NodeCollection>>()
+            .unwrap();
+        assert_eq!(collection.as_node().unwrap(), node);
+
+        let mut reader = Reader::new(Bytes::from(data)).unwrap();
+        assert!(reader.trailing_bytes().is_empty());
+        assert!(matches!(
+            reader.read_node_data(StandardType::String, false),
+            Err(ReaderError::DataBuffer { .. })
+        ));
+    }
+
+    #[test]
+    fn data_first_layout() {
+        let mut node = Node::with_attrs("root", &[("id", "1")]);
+        node.append_child(Node::with_value("a", Value::U8(1)));
+
+        let options = Options::builder()
+            .buffer_layout(BufferLayout::DataFirst)
+            .build();
+        let data = Writer::with_options(options.clone())
+            .to_binary(&node)
+            .unwrap();
+        let standard = node.to_binary().unwrap();
+        assert_ne!(data, standard);
+
+        let expected = Reader::new(Bytes::from(standard))
+            .unwrap()
+            .collect::<Option<NodeCollection>>()
+            .unwrap();
+        let explicit = Reader::with_options(Bytes::from(data.clone()), &options)
+            .unwrap()
+            .collect::<Option<NodeCollection>>()
+            .unwrap();
+        assert!(explicit.semantic_eq(&expected));
+
+        let detected = Reader::new(Bytes::from(data))
+            .unwrap()
+            .collect::<Option<NodeCollection>>()
+            .unwrap();
+        assert!(detected.semantic_eq(&expected));
+    }
+
+    #[test]
+    fn iterator_last_error() {
+        let data = sample();
+
+        let mut reader = Reader::new(Bytes::from(data.clone())).unwrap();
+        assert_eq!(
+            reader
+                .by_ref()
+                .last()
+                .map(|definition| definition.node_type),
+            Some(StandardType::FileEnd)
+        );
+        assert!(reader.next().is_none());
+        assert!(reader.last_error().is_none());
+
+        // Cut the node buffer off before its end markers
+        let len_node = BigEndian::read_u32(&data[4..8]) as usize;
+        let mut truncated = data[..8 + len_node - 4].to_vec();
+        BigEndian::write_u32(&mut truncated[4..8], (len_node - 4) as u32);
+        truncated.extend_from_slice(&[0; 4]);
+
+        let mut reader = Reader::new(Bytes::from(truncated)).unwrap();
+        assert!(reader
+            .by_ref()
+            .all(|definition| definition.node_type != StandardType::FileEnd));
+        assert!(reader.last_error().is_some());
+    }
+
+    #[test]
+    fn root_info() {
+        let data = sample();
+        assert_eq!(
+            Reader::read_root_info(data).unwrap(),
+            ("root".to_owned(), StandardType::NodeStart)
+        );
+
+        let data = Node::with_value("count", Value::U32(1))
+            .to_binary()
+            .unwrap();
+        assert!(matches!(
+            Reader::read_root_info(data),
+            Err(ReaderError::InvalidRootNode {
+                node_type: StandardType::U32
+            })
+        ));
+
+        // A node buffer starting with the end markers
+        let mut data = sample();
+        data[8] = StandardType::FileEnd as u8 | ARRAY_MASK;
+        assert!(matches!(
+            Reader::read_root_info(data),
+            Err(ReaderError::InvalidRootNode {
+                node_type: StandardType::FileEnd
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_counts() {
+        let data = sample();
+        let stats = Reader::validate(data.clone()).unwrap();
+
+        assert_eq!(stats, FileStats {
+            nodes: 4,
+            attributes: 1,
+            bytes_consumed: data.len(),
+        });
+    }
+
+    #[test]
+    fn validate_unbalanced() {
+        const NODE_END: u8 = StandardType::NodeEnd as u8 | ARRAY_MASK;
+        const FILE_END: u8 = StandardType::FileEnd as u8 | ARRAY_MASK;
+
+        let data = sample();
+        let file_end = 8 + data[8..].iter().position(|&b| b == FILE_END).unwrap();
+        let root_end = file_end - 1;
+        assert_eq!(data[root_end], NODE_END);
+
+        let mut extra_end = data.clone();
+        extra_end[file_end] = NODE_END;
+        assert!(matches!(
+            Reader::validate(extra_end),
+            Err(ReaderError::UnbalancedNodeEnd { .. })
+        ));
+
+        let mut missing_end = data;
+        missing_end[root_end] = FILE_END;
+        assert!(matches!(
+            Reader::validate(missing_end),
+            Err(ReaderError::UnclosedNodes { depth: 1 })
+        ));
+    }
+
+    #[test]
+    fn array_flagged_attribute() {
+        let mut data = sample();
+
+        // Header, then the root node's type and its 4 byte compressed key
+        assert_eq!(data[13], StandardType::Attribute as u8);
+        data[13] |= ARRAY_MASK;
+
+        let collection = Reader::new(Bytes::from(data))
+            .unwrap()
+            .collect::<Option<NodeCollection>>()
+            .unwrap();
+        assert!(matches!(
+            collection.as_node(),
+            Err(KbinError::NonArrayableType {
+                node_type: StandardType::Attribute
+            })
+        ));
+
+        assert!(matches!(
+            Value::from_standard_type(StandardType::Attribute, true, &[]),
+            Err(KbinError::NonArrayableType {
+                node_type: StandardType::Attribute
+            })
+        ));
+        assert!(matches!(
+            Value::from_standard_type(StandardType::Time, true, &[0; 4]),
+            Err(KbinError::NonArrayableType {
+                node_type: StandardType::Time
+            })
+        ));
+    }
+
+    #[test]
+    fn read_value_at() {
+        use crate::value::ValueArray;
+
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("a", Value::U8(1)),
+            Node::with_value("b", Value::U8(2)),
+            Node::with_value("c", Value::U32(3)),
+            Node::with_value("d", Value::String("hi".into())),
+            Node::with_value("e", Value::Array(ValueArray::S16(vec![4, 5]))),
+        ]);
+        let data = node.to_binary().unwrap();
+        let mut reader = Reader::new(Bytes::from(data)).unwrap();
+
+        // Start reading, leaving `b` in the middle of a packed slot
+        reader.read_node_definition().unwrap();
+        reader.read_node_definition().unwrap();
+
+        let read = |reader: &mut Reader, offset, node_type, is_array| {
+            reader.read_value_at(offset, node_type, is_array).unwrap()
+        };
+        assert_eq!(read(&mut reader, 1, StandardType::U8, false), Value::U8(2));
+        assert_eq!(read(&mut reader, 0, StandardType::U8, false), Value::U8(1));
+        assert_eq!(
+            read(&mut reader, 4, StandardType::U32, false),
+            Value::U32(3)
+        );
+        assert_eq!(
+            read(&mut reader, 8, StandardType::String, false),
+            Value::String("hi".into())
+        );
+        assert_eq!(
+            read(&mut reader, 16, StandardType::S16, true),
+            Value::Array(ValueArray::S16(vec![4, 5]))
+        );
+        assert!(reader.read_value_at(64, StandardType::U32, false).is_err());
+
+        // Node end of `a`, then `b` and its node end
+        reader.read_node_definition().unwrap();
+        let definition = reader.read_node_definition().unwrap();
+        assert_eq!(definition.value().unwrap(), Value::U8(2));
+        reader.read_node_definition().unwrap();
+        let definition = reader.read_node_definition().unwrap();
+        assert_eq!(definition.value().unwrap(), Value::U32(3));
+    }
+}
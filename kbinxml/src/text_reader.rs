@@ -1,8 +1,10 @@
 use std::borrow::Cow;
+use std::net::Ipv4Addr;
 use std::num::ParseIntError;
 use std::str::{self, Utf8Error};
 
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::Bytes;
+use quick_xml::escape::unescape;
 use quick_xml::events::attributes::Attributes;
 use quick_xml::events::{BytesStart, BytesText, Event};
 use quick_xml::name::QName;
@@ -11,12 +13,65 @@ use quick_xml::Reader;
 use snafu::{ResultExt, Snafu};
 
 use crate::encoding_type::{EncodingError, EncodingType};
+use crate::error::KbinError;
 use crate::node::{Key, NodeCollection, NodeData, NodeDefinition};
 use crate::node_types::{StandardType, UnknownKbinType};
+use crate::options::Options;
+use crate::types::saturate_integers;
 use crate::value::Value;
 
 const EMPTY_STRING_DATA: &[u8] = &[0];
 
+/// Attribute holding the original `__type` of a node read with an unknown type
+pub(crate) const UNKNOWN_TYPE_ATTRIBUTE: &str = "__unknown_type";
+
+/// Guess the type of a node without a `__type` attribute from its text.
+///
+/// Decimal integers that fit in an `s32` are read as `S32` and dotted quads as
+/// `Ip4`. A lone `0` or `1` could equally be a boolean, and leading zeros would
+/// be lost when written back, so those stay strings along with everything else.
+fn infer_type(data: &str) -> Option<StandardType> {
+    let digits = data.strip_prefix('-').unwrap_or(data);
+    let is_integer = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+
+    // Covers `0`, `-0` and leading zeros as well as `1`
+    let is_ambiguous = data == "1" || digits.starts_with('0');
+
+    if is_integer && !is_ambiguous && data.parse::<i32>().is_ok() {
+        Some(StandardType::S32)
+    } else if !is_integer && data.parse::<Ipv4Addr>().is_ok() {
+        Some(StandardType::Ip4)
+    } else {
+        None
+    }
+}
+
+/// Decode raw text or attribute value bytes with the declared encoding.
+/// `quick_xml` assumes UTF-8, so other encodings are decoded here from the raw
+/// bytes instead.
+fn decode_raw(encoding: EncodingType, raw: &[u8]) -> Result<Cow<'_, str>, TextReaderError> {
+    let text = match encoding {
+        EncodingType::None | EncodingType::ASCII | EncodingType::UTF_8 => {
+            Cow::Borrowed(str::from_utf8(raw)?)
+        },
+        encoding => Cow::Owned(
+            encoding
+                .decode_bytes(raw)
+                .context(TextEncodingSnafu { encoding })?,
+        ),
+    };
+
+    Ok(text)
+}
+
+/// Decode raw text bytes with the declared encoding, then unescape them.
+fn decode_text(encoding: EncodingType, raw: &[u8]) -> Result<String, TextReaderError> {
+    let text = decode_raw(encoding, raw)?;
+    let text = unescape(&text).map_err(QuickXmlError::from)?;
+
+    Ok(text.into_owned())
+}
+
 #[derive(Debug, Snafu)]
 pub enum TextReaderError {
     #[snafu(display("Invalid kbin type found"))]
@@ -41,9 +96,18 @@ pub enum TextReaderError {
     #[snafu(display("No node data found"))]
     NoNodeData,
 
+    #[snafu(display("Duplicate attribute found: {}", key))]
+    DuplicateAttribute { key: String },
+
     #[snafu(display("Failed to interpret byte slice as UTF-8"))]
     Utf8 { source: Utf8Error },
 
+    #[snafu(display("Failed to convert text with encoding {}", encoding))]
+    TextEncoding {
+        encoding: EncodingType,
+        source: EncodingError,
+    },
+
     #[snafu(display("Failed to decode value from string for node type {}", node_type))]
     ValueDecode {
         node_type: StandardType,
@@ -60,6 +124,19 @@ pub enum TextReaderError {
 
     #[snafu(display("Failed to handle XML operation"))]
     Xml { source: QuickXmlError },
+
+    #[snafu(display(
+        "Invalid XML at line {}, column {} (offset: {})",
+        line,
+        column,
+        position
+    ))]
+    InvalidXml {
+        position: usize,
+        line: usize,
+        column: usize,
+        source: QuickXmlError,
+    },
 }
 
 impl From<Utf8Error> for TextReaderError {
@@ -76,21 +153,39 @@ impl From<QuickXmlError> for TextReaderError {
     }
 }
 
+/// The node type, array count, binary size and regular attributes of an element
+type ParsedAttributes = (
+    StandardType,
+    Option<usize>,
+    Option<usize>,
+    Vec<NodeDefinition>,
+);
+
 pub struct TextXmlReader<'a> {
+    input: &'a [u8],
     xml_reader: Reader<&'a [u8]>,
     encoding: EncodingType,
+    forced_encoding: bool,
+    options: Options,
 
-    stack: Vec<(NodeCollection, usize, Option<usize>)>,
+    stack: Vec<(NodeCollection, Option<usize>, Option<usize>)>,
 }
 
 impl<'a> TextXmlReader<'a> {
     pub fn new(input: &'a [u8]) -> Self {
+        Self::with_options(input, Options::default())
+    }
+
+    pub fn with_options(input: &'a [u8], options: Options) -> Self {
         let mut xml_reader = Reader::from_reader(input);
         xml_reader.trim_text(true);
 
         Self {
+            input,
             xml_reader,
             encoding: EncodingType::UTF_8,
+            forced_encoding: false,
+            options,
 
             // Most kbinxml files that I have come across do not have too
             // many inner layers.
@@ -103,19 +198,46 @@ impl<'a> TextXmlReader<'a> {
         self.encoding
     }
 
-    fn parse_attribute(&self, key: &[u8], value: &[u8]) -> Result<NodeDefinition, TextReaderError> {
-        let mut value = BytesMut::from(value);
+    /// Read the input with `encoding`, ignoring the encoding of the XML
+    /// declaration, if any.
+    pub fn force_encoding(&mut self, encoding: EncodingType) {
+        self.encoding = encoding;
+        self.forced_encoding = true;
+    }
 
-        // Add the trailing null byte that kbin has at the end of strings
-        value.reserve(1);
-        value.put_u8(0);
+    /// Attach the reader's position in the input, as a byte offset and a
+    /// 1-based line and column, to an error from `quick_xml`.
+    fn xml_error(&self, source: QuickXmlError) -> TextReaderError {
+        let position = self.xml_reader.buffer_position().min(self.input.len());
+        let before = &self.input[..position];
+
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        let column = match before.iter().rposition(|&b| b == b'\n') {
+            Some(index) => position - index,
+            None => position + 1,
+        };
+
+        TextReaderError::InvalidXml {
+            position,
+            line,
+            column,
+            source,
+        }
+    }
+
+    fn parse_attribute(&self, key: &[u8], value: &str) -> Result<NodeDefinition, TextReaderError> {
+        // `encode_bytes` adds the trailing null byte that kbin has at the end of strings
+        let encoding = self.encoding;
+        let value = encoding
+            .encode_bytes(value)
+            .context(TextEncodingSnafu { encoding })?;
 
         let data = NodeData::Some {
             key: Key::Uncompressed {
-                encoding: self.encoding,
+                encoding,
                 data: Bytes::from(key.to_vec()),
             },
-            value_data: value.freeze(),
+            value_data: Bytes::from(value),
         };
 
         // `Attribute` nodes do not have the `is_array` flag set
@@ -127,41 +249,64 @@ impl<'a> TextXmlReader<'a> {
         ))
     }
 
-    fn parse_attributes(
-        &self,
-        attrs: Attributes<'a>,
-    ) -> Result<(StandardType, usize, Option<usize>, Vec<NodeDefinition>), TextReaderError> {
+    fn parse_attributes(&self, attrs: Attributes<'a>) -> Result<ParsedAttributes, TextReaderError> {
         let mut node_type = None;
-        let mut count = 0;
+        let mut count = None;
         let mut size = None;
         let mut attributes = Vec::new();
+        let mut keys: Vec<&'a [u8]> = Vec::new();
+        let mut unknown_type = None;
+
+        // Duplicate attributes are handled below according to the options
+        let mut attrs = attrs;
+        attrs.with_checks(false);
 
         for attr in attrs {
             match attr {
                 Ok(attr) => {
-                    let value = match attr.unescape_value() {
-                        Ok(v) => v,
+                    let value = decode_raw(self.encoding, &attr.value)?;
+                    let value = match unescape(&value) {
+                        Ok(v) => v.into_owned(),
                         Err(e) => {
                             error!("Error decoding attribute value: {:?}", e);
-                            Cow::Borrowed(str::from_utf8(&attr.value)?)
+                            value.into_owned()
                         },
                     };
 
                     if attr.key == QName(b"__type") {
-                        node_type =
-                            Some(StandardType::from_name(&value).context(InvalidKbinTypeSnafu)?);
+                        match StandardType::from_name(&value) {
+                            Ok(value) => node_type = Some(value),
+                            Err(_) if self.options.lenient_unknown_types => {
+                                warn!("Unknown kbin type, reading node as string: {}", value);
+                                unknown_type = Some(value.clone());
+                            },
+                            Err(e) => return Err(TextReaderError::InvalidKbinType { source: e }),
+                        };
                     } else if attr.key == QName(b"__count") {
                         let num_count = value.parse::<u32>().context(ParseArrayCountSnafu)?;
 
-                        count = num_count as usize;
+                        count = Some(num_count as usize);
                     } else if attr.key == QName(b"__size") {
                         let value = value.parse::<usize>().context(ParseBinarySizeSnafu)?;
 
                         size = Some(value);
                     } else {
-                        let definition =
-                            self.parse_attribute(attr.key.into_inner(), &value.as_bytes())?;
-                        attributes.push(definition);
+                        let key = attr.key.into_inner();
+                        let definition = self.parse_attribute(key, &value)?;
+
+                        // Keep the first position but the last value, like `IndexMap::insert`
+                        match keys.iter().position(|existing| *existing == key) {
+                            Some(_) if self.options.duplicate_attribute_error => {
+                                return Err(TextReaderError::DuplicateAttribute {
+                                    key: String::from_utf8_lossy(key).into_owned(),
+                                });
+                            },
+                            Some(index) => attributes[index] = definition,
+                            None => {
+                                keys.push(key);
+                                attributes.push(definition);
+                            },
+                        };
                     }
                 },
                 Err(e) => {
@@ -170,6 +315,15 @@ impl<'a> TextXmlReader<'a> {
             };
         }
 
+        // Nodes with an unknown type keep their raw text as a `String`, so the array
+        // count and binary size do not apply
+        if let Some(unknown_type) = unknown_type {
+            count = None;
+            size = None;
+            attributes
+                .push(self.parse_attribute(UNKNOWN_TYPE_ATTRIBUTE.as_bytes(), &unknown_type)?);
+        }
+
         let node_type = match node_type {
             Some(node_type) => node_type,
             None => {
@@ -185,9 +339,24 @@ impl<'a> TextXmlReader<'a> {
     fn handle_start(
         &self,
         e: BytesStart,
-    ) -> Result<(NodeCollection, usize, Option<usize>), TextReaderError> {
+    ) -> Result<(NodeCollection, Option<usize>, Option<usize>), TextReaderError> {
         let (node_type, count, size, attributes) = self.parse_attributes(e.attributes())?;
-        let is_array = count > 0;
+
+        // Any `__count`, including `0` for an empty array, marks an array
+        let is_array = count.is_some();
+
+        // Repeated strings are sibling nodes in kbin, never an array value
+        if is_array &&
+            matches!(
+                node_type,
+                StandardType::String | StandardType::Binary | StandardType::Time
+            )
+        {
+            return Err(TextReaderError::ValueDecode {
+                node_type,
+                source: Box::new(KbinError::NonArrayableType { node_type }),
+            });
+        }
 
         // Stub the value for now, handle with `Event::Text`.
         let value_data = match node_type {
@@ -211,23 +380,38 @@ impl<'a> TextXmlReader<'a> {
     fn handle_text(
         event: BytesText,
         definition: &mut NodeDefinition,
-        count: usize,
+        count: Option<usize>,
         size: Option<usize>,
+        options: &Options,
     ) -> Result<(), TextReaderError> {
-        let data = event.unescape()?;
+        let encoding = definition.encoding();
+        let data = decode_text(encoding, &event)?;
+
+        // An explicit `__type` always takes precedence over inference
+        if definition.node_type == StandardType::NodeStart && options.infer_types {
+            if let Some(node_type) = infer_type(&data) {
+                definition.node_type = node_type;
+            }
+        }
+
         let data = match definition.node_type {
             StandardType::String | StandardType::NodeStart => {
-                let mut data = BytesMut::from(&*data);
-
-                // Add the trailing null byte that kbin has at the end of strings
-                data.reserve(1);
-                data.put_u8(0);
+                // `encode_bytes` adds the trailing null byte that kbin has at the end of strings
+                let data = encoding
+                    .encode_bytes(&data)
+                    .context(TextEncodingSnafu { encoding })?;
 
-                data.freeze()
+                Bytes::from(data)
             },
             node_type => {
-                let value = Value::from_string(node_type, &data, definition.is_array, count)
-                    .context(ValueDecodeSnafu { node_type })?;
+                let data = if options.saturate_integers {
+                    saturate_integers(node_type, &data).unwrap_or(data)
+                } else {
+                    data
+                };
+                let value =
+                    Value::from_string(node_type, &data, definition.is_array, count.unwrap_or(0))
+                        .context(ValueDecodeSnafu { node_type })?;
 
                 // The read number of bytes must match the size attribute, if set
                 if let Value::Binary(data) = &value {
@@ -266,7 +450,12 @@ impl<'a> TextXmlReader<'a> {
 
     pub fn as_node_collection(&mut self) -> Result<Option<NodeCollection>, TextReaderError> {
         loop {
-            match self.xml_reader.read_event()? {
+            let event = self
+                .xml_reader
+                .read_event()
+                .map_err(|e| self.xml_error(e))?;
+
+            match event {
                 Event::Start(e) => {
                     let start = self.handle_start(e)?;
                     self.stack.push(start);
@@ -274,7 +463,7 @@ impl<'a> TextXmlReader<'a> {
                 Event::Text(e) => {
                     if let Some((ref mut collection, ref count, ref size)) = self.stack.last_mut() {
                         let base = collection.base_mut();
-                        Self::handle_text(e, base, *count, *size)?;
+                        Self::handle_text(e, base, *count, *size, &self.options)?;
                     }
                 },
                 Event::End(_) => {
@@ -289,7 +478,10 @@ impl<'a> TextXmlReader<'a> {
                 },
                 Event::Empty(e) => {
                     let (collection, count, size) = self.handle_start(e)?;
-                    assert!(count == 0, "empty node should not signal an array");
+                    assert!(
+                        count.unwrap_or(0) == 0,
+                        "empty node should not signal a non-empty array"
+                    );
                     assert!(
                         size.is_none() || size == Some(0),
                         "empty node should not signal binary data"
@@ -303,7 +495,7 @@ impl<'a> TextXmlReader<'a> {
                         return Ok(Some(collection));
                     }
                 },
-                Event::Decl(e) => {
+                Event::Decl(e) if !self.forced_encoding => {
                     if let Some(encoding) = e.encoding() {
                         self.encoding =
                             EncodingType::from_label(&encoding?).context(InvalidEncodingSnafu)?;
@@ -317,3 +509,226 @@ impl<'a> TextXmlReader<'a> {
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUPLICATE: &[u8] = br#"<n a="1" b="x" a="2"/>"#;
+
+    #[test]
+    fn duplicate_attribute_last_wins() {
+        let (collection, _) = crate::from_text_xml(DUPLICATE).unwrap();
+
+        assert_eq!(collection.attributes().len(), 2);
+        assert_eq!(collection.attr("a").unwrap().unwrap(), "2");
+
+        let node = collection.as_node().unwrap();
+        let keys: Vec<_> = node.attributes().keys().collect();
+        assert_eq!(keys, ["a", "b"]);
+    }
+
+    #[test]
+    fn duplicate_attribute_error() {
+        let options = Options::builder().duplicate_attribute_error(true).build();
+        let mut reader = TextXmlReader::with_options(DUPLICATE, options);
+
+        match reader.as_node_collection() {
+            Err(TextReaderError::DuplicateAttribute { key }) => assert_eq!(key, "a"),
+            _ => panic!("expected duplicate attribute error"),
+        }
+    }
+
+    #[test]
+    fn shift_jis_input() {
+        let text = r#"<?xml version="1.0" encoding="SHIFT_JIS"?>
+            <root 属性="日本">
+                <名前>こんにちは &amp; 世界</名前>
+                <v __type="u8">1</v>
+            </root>"#;
+        let input = EncodingType::SHIFT_JIS.encode_bytes(text).unwrap();
+        let input = &input[..input.len() - 1];
+        assert!(str::from_utf8(input).is_err());
+
+        let (collection, encoding) = crate::from_text_xml(input).unwrap();
+        assert_eq!(encoding, EncodingType::SHIFT_JIS);
+
+        let node = collection.as_node().unwrap();
+        assert_eq!(node.attributes()["属性"], "日本");
+        assert_eq!(
+            node.get_child("名前").unwrap().value(),
+            Some(&Value::String("こんにちは & 世界".into()))
+        );
+        assert_eq!(node.get_child("v").unwrap().value(), Some(&Value::U8(1)));
+    }
+
+    #[test]
+    fn attribute_unescape_fallback() {
+        let input = br#"<root a="x &bogus; y" b="&lt;"/>"#;
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+
+        let node = collection.as_node().unwrap();
+        assert_eq!(node.attributes()["a"], "x &bogus; y");
+        assert_eq!(node.attributes()["b"], "<");
+    }
+
+    #[test]
+    fn forced_encoding() {
+        let text = "<root><名前>日本</名前></root>";
+        let input = EncodingType::SHIFT_JIS.encode_bytes(text).unwrap();
+        let input = &input[..input.len() - 1];
+
+        // Without a declaration, the input is read as UTF-8
+        assert!(crate::from_text_xml(input).is_err());
+
+        let (collection, encoding) =
+            crate::from_text_xml_with_encoding(input, EncodingType::SHIFT_JIS).unwrap();
+        assert_eq!(encoding, EncodingType::SHIFT_JIS);
+        assert_eq!(collection.base().encoding(), EncodingType::SHIFT_JIS);
+        assert_eq!(
+            collection
+                .as_node()
+                .unwrap()
+                .get_child("名前")
+                .unwrap()
+                .value(),
+            Some(&Value::String("日本".into()))
+        );
+
+        // The declaration is ignored
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?><root>日本</root>"#;
+        let input = EncodingType::EUC_JP.encode_bytes(text).unwrap();
+        let input = &input[..input.len() - 1];
+        let (collection, encoding) =
+            crate::from_text_xml_with_encoding(input, EncodingType::EUC_JP).unwrap();
+        assert_eq!(encoding, EncodingType::EUC_JP);
+        assert_eq!(
+            collection.base().value().unwrap(),
+            Value::String("日本".into())
+        );
+    }
+
+    #[test]
+    fn invalid_xml_position() {
+        const INPUT: &[u8] = b"<root>\n  <a>1</a>\n  <b>2</c>\n</root>";
+
+        match TextXmlReader::new(INPUT).as_node_collection() {
+            Err(TextReaderError::InvalidXml { line, column, .. }) => {
+                assert_eq!((line, column), (3, 9));
+            },
+            result => panic!("expected invalid XML error, got: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn infer_types() {
+        const INPUT: &[u8] = br#"<root>
+            <a>42</a>
+            <b>-7</b>
+            <c>10.0.0.1</c>
+            <d>1</d>
+            <e>007</e>
+            <f>99999999999</f>
+            <g>text</g>
+            <h __type="str">5</h>
+        </root>"#;
+
+        let options = Options::builder().infer_types(true).build();
+        let mut reader = TextXmlReader::with_options(INPUT, options);
+        let node = reader
+            .as_node_collection()
+            .unwrap()
+            .unwrap()
+            .as_node()
+            .unwrap();
+        let value = |key| node.get_child(key).unwrap().value().unwrap().clone();
+
+        assert_eq!(value("a"), Value::S32(42));
+        assert_eq!(value("b"), Value::S32(-7));
+        assert_eq!(value("c"), Value::Ip4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(value("d"), Value::String("1".into()));
+        assert_eq!(value("e"), Value::String("007".into()));
+        assert_eq!(value("f"), Value::String("99999999999".into()));
+        assert_eq!(value("g"), Value::String("text".into()));
+        assert_eq!(value("h"), Value::String("5".into()));
+
+        let mut reader = TextXmlReader::new(INPUT);
+        let node = reader
+            .as_node_collection()
+            .unwrap()
+            .unwrap()
+            .as_node()
+            .unwrap();
+        assert_eq!(
+            node.get_child("a").unwrap().value(),
+            Some(&Value::String("42".into()))
+        );
+    }
+
+    #[test]
+    fn saturate_integers() {
+        const INPUT: &[u8] =
+            br#"<root><a __type="u8">300</a><b __type="s8" __count="2">-200 5</b></root>"#;
+
+        assert!(TextXmlReader::new(INPUT).as_node_collection().is_err());
+
+        let options = Options::builder().saturate_integers(true).build();
+        let mut reader = TextXmlReader::with_options(INPUT, options);
+        let node = reader
+            .as_node_collection()
+            .unwrap()
+            .unwrap()
+            .as_node()
+            .unwrap();
+
+        assert_eq!(node.get_child("a").unwrap().value(), Some(&Value::U8(255)));
+        assert_eq!(
+            node.get_child("b").unwrap().value(),
+            Some(&Value::Array(crate::ValueArray::S8(vec![-128, 5])))
+        );
+    }
+
+    #[test]
+    fn lenient_unknown_types() {
+        const INPUT: &[u8] = br#"<root><v __type="vendor_t" __count="2">1 2</v></root>"#;
+
+        assert!(matches!(
+            TextXmlReader::new(INPUT).as_node_collection(),
+            Err(TextReaderError::InvalidKbinType { .. })
+        ));
+
+        let options = Options::builder().lenient_unknown_types(true).build();
+        let mut reader = TextXmlReader::with_options(INPUT, options);
+        let node = reader
+            .as_node_collection()
+            .unwrap()
+            .unwrap()
+            .as_node()
+            .unwrap();
+
+        let v = node.get_child("v").unwrap();
+        assert_eq!(v.value(), Some(&Value::String("1 2".into())));
+        assert_eq!(v.attributes()[UNKNOWN_TYPE_ATTRIBUTE], "vendor_t");
+    }
+
+    #[test]
+    fn string_array() {
+        const INPUT: &[u8] = br#"<root><v __type="str" __count="2">a b</v></root>"#;
+
+        match TextXmlReader::new(INPUT).as_node_collection() {
+            Err(TextReaderError::ValueDecode { source, .. }) => {
+                assert!(matches!(*source, KbinError::NonArrayableType {
+                    node_type: StandardType::String
+                }))
+            },
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // The sibling node representation round-trips
+        let node = crate::Node::string_list("v", &["a", "b"]);
+        let text = crate::to_text_xml(&node).unwrap();
+        let (collection, _) = crate::from_text_xml(&text).unwrap();
+        assert_eq!(collection.as_node().unwrap(), node);
+        assert_eq!(node.child_keys().collect::<Vec<_>>(), ["item", "item"]);
+    }
+}
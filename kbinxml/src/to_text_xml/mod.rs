@@ -1,10 +1,13 @@
+use std::fmt::Write as _;
 use std::io::{Cursor, Write};
 
 use quick_xml::events::{BytesDecl, Event};
 use quick_xml::Writer;
 
-use crate::encoding_type::EncodingType;
+use crate::encoding_type::{EncodingError, EncodingType};
 use crate::error::KbinError;
+use crate::options::Options;
+use crate::value::Value;
 
 mod node;
 mod node_collection;
@@ -12,32 +15,278 @@ mod node_collection;
 pub trait ToTextXml {
     fn encoding(&self) -> EncodingType;
     fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), KbinError>;
+
+    /// Write like `write`, honoring the text formatting flags in `options`. The
+    /// default implementation ignores `options`.
+    fn write_with_options<W: Write>(
+        &self,
+        writer: &mut Writer<W>,
+        options: &Options,
+    ) -> Result<(), KbinError> {
+        let _ = options;
+        self.write(writer)
+    }
+}
+
+/// Format a value for the text contents of an element, honoring the text
+/// formatting flags in `options`.
+fn value_text(value: &Value, options: &Options) -> String {
+    match value {
+        Value::Binary(data) if options.uppercase_hex => {
+            let mut output = String::with_capacity(data.len() * 2);
+            for n in data {
+                let _ = write!(output, "{:02X}", n);
+            }
+            output
+        },
+        value if options.shortest_floats && value.standard_type().is_floating() => {
+            format!("{:#}", value)
+        },
+        value if options.boolean_words && value.standard_type().is_boolean() => value
+            .to_string()
+            .split(' ')
+            .map(|part| match part {
+                "0" => "false",
+                "1" => "true",
+                part => part,
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        value => value.to_string(),
+    }
 }
 
 pub struct TextXmlWriter {
     xml_writer: Writer<Cursor<Vec<u8>>>,
+    options: Options,
+    encoding: Option<EncodingType>,
 }
 
 impl TextXmlWriter {
+    /// Create a writer with the default options whose output uses the encoding
+    /// of the value being written.
     pub fn new() -> Self {
+        Self::with_encoding(Options::default(), None)
+    }
+
+    /// Create a writer whose output and declaration use the encoding in
+    /// `options`.
+    pub fn with_options(options: Options) -> Self {
+        let encoding = options.encoding;
+
+        Self::with_encoding(options, Some(encoding))
+    }
+
+    fn with_encoding(options: Options, encoding: Option<EncodingType>) -> Self {
         let inner = Cursor::new(Vec::new());
         let xml_writer = Writer::new_with_indent(inner, b' ', 2);
 
-        Self { xml_writer }
+        Self {
+            xml_writer,
+            options,
+            encoding,
+        }
     }
 
     pub fn into_text_xml<T>(mut self, value: &T) -> Result<Vec<u8>, KbinError>
     where
         T: ToTextXml,
     {
-        if let Some(encoding) = value.encoding().name() {
-            let header = BytesDecl::new("1.0", Some(encoding), None);
+        let encoding = self.encoding.unwrap_or_else(|| value.encoding());
+
+        // Encodings without a name are written out as UTF-8 text. No byte order mark
+        // is written, so the declaration is always the first thing in the output.
+        let name = match encoding.name() {
+            Some(name) => Some(name),
+            None if self.options.always_declaration => Some("UTF-8"),
+            None => None,
+        };
+        if let Some(name) = name {
+            let header = BytesDecl::new("1.0", Some(name), None);
 
             self.xml_writer.write_event(Event::Decl(header))?;
         }
 
-        value.write(&mut self.xml_writer)?;
+        value.write_with_options(&mut self.xml_writer, &self.options)?;
+
+        // The document is written as UTF-8 and converted to the output encoding in
+        // one pass at the end
+        let text = self.xml_writer.into_inner().into_inner();
+        match encoding {
+            EncodingType::None | EncodingType::UTF_8 => Ok(text),
+            encoding => {
+                let text = String::from_utf8(text)
+                    .map_err(|source| EncodingError::InvalidUtf8 { source })?;
+
+                encoding.encode_bytes_raw(&text).map_err(Into::into)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::node::Node;
+
+    struct Ascii(Node);
+
+    impl ToTextXml for Ascii {
+        fn encoding(&self) -> EncodingType {
+            EncodingType::ASCII
+        }
+
+        fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), KbinError> {
+            self.0.write(writer)
+        }
+    }
+
+    #[test]
+    fn always_declaration() {
+        let node = Ascii(Node::with_value("n", Value::U8(1)));
+
+        let output = crate::to_text_xml(&node).unwrap();
+        assert!(output.starts_with(b"<n"));
+
+        let options = Options::builder()
+            .encoding(EncodingType::ASCII)
+            .always_declaration(true)
+            .build();
+        let output = crate::to_text_xml_with_options(options, &node).unwrap();
+        assert!(output.starts_with(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+
+        let output = crate::to_text_xml(&node.0).unwrap();
+        assert!(output.starts_with(b"<?xml"));
+    }
+
+    #[test]
+    fn array_count_round_trip() {
+        use crate::value::ValueArray;
+
+        let mut root = Node::new("root");
+        root.append_child(Node::with_value("scalar", Value::U8(1)));
+        root.append_child(Node::with_value("pair", Value::U8_2([1, 2])));
+        root.append_child(Node::with_value(
+            "array",
+            Value::Array(ValueArray::U8(vec![1, 2, 3])),
+        ));
+        root.append_child(Node::with_value(
+            "pairs",
+            Value::Array(ValueArray::U8_2(vec![[1, 2], [3, 4]])),
+        ));
+        root.append_child(Node::with_value(
+            "empty",
+            Value::Array(ValueArray::S32(vec![])),
+        ));
+
+        let binary = crate::to_binary(&root).unwrap();
+        let (collection, _) = crate::from_slice(&binary).unwrap();
+
+        // Both text writers must agree
+        let text = crate::to_text_xml(&root).unwrap();
+        assert_eq!(crate::to_text_xml(&collection).unwrap(), text);
+
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains(r#"<scalar __type="u8">"#));
+        assert!(text.contains(r#"<pair __type="2u8">"#));
+        assert!(text.contains(r#"<array __count="3" __type="u8">"#));
+        assert!(text.contains(r#"<pairs __count="2" __type="2u8">"#));
+        assert!(text.contains(r#"__count="0" __type="s32""#));
+
+        let (collection, _) = crate::from_text_xml(text.as_bytes()).unwrap();
+        assert_eq!(collection.as_node().unwrap(), root);
+        assert_eq!(crate::to_binary(&collection).unwrap(), binary);
+    }
+
+    #[test]
+    fn uppercase_hex() {
+        let node = Node::with_value("data", Value::Binary(vec![0xab, 0x01, 0xcd]));
+
+        let text = String::from_utf8(crate::to_text_xml(&node).unwrap()).unwrap();
+        assert!(text.contains(">ab01cd<"));
+
+        let options = Options::builder().uppercase_hex(true).build();
+        let text = crate::to_text_xml_with_options(options, &node).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains(">AB01CD<"));
+    }
+
+    #[test]
+    fn shortest_floats() {
+        use crate::value::ValueArray;
+
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("a", Value::Double(-0.0)),
+            Node::with_value("b", Value::Double(1e-20)),
+            Node::with_value("c", Value::Double(f64::MAX)),
+            Node::with_value("d", Value::Float(-1.5e-30)),
+            Node::with_value("e", Value::Float2([0.1, -0.0])),
+            Node::with_value(
+                "f",
+                Value::Array(ValueArray::Double3(vec![[1e300, 2.5, -1e-300]])),
+            ),
+        ]);
+        let binary = crate::to_binary(&node).unwrap();
+
+        // The default 6 decimal places lose data
+        let text = crate::to_text_xml(&node).unwrap();
+        let (collection, _) = crate::from_text_xml(&text).unwrap();
+        assert_ne!(crate::to_binary(&collection).unwrap(), binary);
+
+        let options = Options::builder().shortest_floats(true).build();
+        let text = crate::to_text_xml_with_options(options, &node).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains(">-0.0<"));
+        assert!(text.contains(">1e-20<"));
+        assert!(text.contains(">0.1 -0.0<"));
+
+        let (collection, _) = crate::from_text_xml(text.as_bytes()).unwrap();
+        assert_eq!(crate::to_binary(&collection).unwrap(), binary);
+    }
+
+    #[test]
+    fn boolean_words() {
+        use crate::node_types::StandardType;
+        use crate::value::ValueArray;
+
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("a", Value::Boolean(true)),
+            Node::with_value("b", Value::Boolean2([false, true])),
+            Node::with_value("c", Value::Array(ValueArray::Boolean(vec![true, false]))),
+        ]);
+
+        let text = String::from_utf8(crate::to_text_xml(&node).unwrap()).unwrap();
+        assert!(text.contains(">0 1<"));
+
+        let options = Options::builder().boolean_words(true).build();
+        let text = crate::to_text_xml_with_options(options, &node).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains(r#"<a __type="bool">true</a>"#));
+        assert!(text.contains(">false true<"));
+        assert!(text.contains(">true false<"));
+
+        let (collection, _) = crate::from_text_xml(text.as_bytes()).unwrap();
+        assert_eq!(collection.as_node().unwrap(), node);
+
+        let options = Options::builder()
+            .boolean_words(true)
+            .shortest_floats(true)
+            .build();
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("a", Value::Boolean(true)),
+            Node::with_value("b", Value::Double(1e-20)),
+            Node::with_value("c", Value::Array(ValueArray::Float(vec![-0.0, 0.5]))),
+        ]);
+        let text = crate::to_text_xml_with_options(options, &node).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains(r#"<a __type="bool">true</a>"#));
+        assert!(text.contains(">1e-20<"));
+        assert!(text.contains(">-0.0 0.5<"));
 
-        Ok(self.xml_writer.into_inner().into_inner())
+        assert_eq!(
+            Value::from_string(StandardType::Boolean, "true false 1", true, 3).unwrap(),
+            Value::Array(ValueArray::Boolean(vec![true, false, true]))
+        );
     }
 }
@@ -10,7 +10,8 @@ use crate::encoding_type::EncodingType;
 use crate::error::KbinError;
 use crate::node::Node;
 use crate::node_types::StandardType;
-use crate::to_text_xml::ToTextXml;
+use crate::options::Options;
+use crate::to_text_xml::{value_text, ToTextXml};
 use crate::value::Value;
 
 impl ToTextXml for Node {
@@ -20,6 +21,14 @@ impl ToTextXml for Node {
     }
 
     fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), KbinError> {
+        self.write_with_options(writer, &Options::default())
+    }
+
+    fn write_with_options<W: Write>(
+        &self,
+        writer: &mut Writer<W>,
+        options: &Options,
+    ) -> Result<(), KbinError> {
         let key = self.key();
         let mut elem = BytesStart::new(key);
 
@@ -65,7 +74,7 @@ impl ToTextXml for Node {
         let start_elem = if let Some(value) = self.value() {
             writer.write_event(Event::Start(elem))?;
 
-            let value = value.to_string();
+            let value = value_text(value, options);
             let elem = BytesText::new(&value);
             writer.write_event(Event::Text(elem))?;
 
@@ -87,7 +96,7 @@ impl ToTextXml for Node {
         }
 
         for child in self.children() {
-            child.write(writer)?;
+            child.write_with_options(writer, options)?;
         }
 
         if has_value || has_children {
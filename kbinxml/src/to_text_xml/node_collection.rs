@@ -10,7 +10,8 @@ use crate::encoding_type::EncodingType;
 use crate::error::KbinError;
 use crate::node::NodeCollection;
 use crate::node_types::StandardType;
-use crate::to_text_xml::ToTextXml;
+use crate::options::Options;
+use crate::to_text_xml::{value_text, ToTextXml};
 
 impl ToTextXml for NodeCollection {
     /// At the moment, decoding the value of a `NodeDefinition` will decode
@@ -20,6 +21,14 @@ impl ToTextXml for NodeCollection {
     }
 
     fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), KbinError> {
+        self.write_with_options(writer, &Options::default())
+    }
+
+    fn write_with_options<W: Write>(
+        &self,
+        writer: &mut Writer<W>,
+        options: &Options,
+    ) -> Result<(), KbinError> {
         let base = self.base();
         let key = base.key()?.ok_or(KbinError::InvalidState)?;
         let value = match base.value() {
@@ -75,7 +84,7 @@ impl ToTextXml for NodeCollection {
             Some(value) => {
                 writer.write_event(Event::Start(elem))?;
 
-                let value = value.to_string();
+                let value = value_text(&value, options);
                 let elem = BytesText::new(&value);
                 writer.write_event(Event::Text(elem))?;
 
@@ -97,7 +106,7 @@ impl ToTextXml for NodeCollection {
         }
 
         for child in self.children() {
-            child.write(writer)?;
+            child.write_with_options(writer, options)?;
         }
 
         if has_value || has_children {
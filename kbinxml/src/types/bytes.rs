@@ -55,7 +55,7 @@ impl FromKbinBytes for bool {
     }
 }
 
-impl<'a> IntoKbinBytes for &'a [u8] {
+impl IntoKbinBytes for &[u8] {
     fn write_kbin_bytes<B: BufMut>(self, buf: &mut B) {
         buf.put(self);
     }
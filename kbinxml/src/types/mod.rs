@@ -3,3 +3,4 @@ mod string;
 
 pub use self::bytes::{FromKbinBytes, IntoKbinBytes};
 pub use self::string::FromKbinString;
+pub(crate) use self::string::{integers_from_radix, saturate_integers};
@@ -1,24 +1,107 @@
 use std::error::Error;
 use std::net::Ipv4Addr;
+use std::num::IntErrorKind;
 use std::str::FromStr;
 
 use snafu::ResultExt;
 
 use crate::error::*;
+use crate::node_types::StandardType;
 
 pub trait FromKbinString: Sized {
     fn from_kbin_string(input: &str) -> Result<Self>;
 }
 
-fn space_check(input: &str) -> Result<()> {
-    // check for space character
-    if input.find(' ').is_some() {
-        return Err(KbinError::InvalidState);
+/// Reject whitespace in the text of a single value, which would otherwise be
+/// reported as a generic parse error. The error has the kbin name of `node_type`.
+fn space_check(node_type: StandardType, input: &str) -> Result<()> {
+    if input.contains(char::is_whitespace) {
+        return Err(KbinError::UnexpectedWhitespace {
+            node_type: node_type.name,
+        });
     }
 
     Ok(())
 }
 
+fn integer_bounds(node_type: StandardType) -> Option<(i128, i128)> {
+    use StandardType::*;
+
+    let bounds = match node_type {
+        S8 | S8_2 | S8_3 | S8_4 | Vs8 => (i8::MIN as i128, i8::MAX as i128),
+        U8 | U8_2 | U8_3 | U8_4 | Vu8 => (0, u8::MAX as i128),
+        S16 | S16_2 | S16_3 | S16_4 | Vs16 => (i16::MIN as i128, i16::MAX as i128),
+        U16 | U16_2 | U16_3 | U16_4 | Vu16 => (0, u16::MAX as i128),
+        S32 | S32_2 | S32_3 | S32_4 => (i32::MIN as i128, i32::MAX as i128),
+        U32 | U32_2 | U32_3 | U32_4 => (0, u32::MAX as i128),
+        S64 | S64_2 | S64_3 | S64_4 => (i64::MIN as i128, i64::MAX as i128),
+        U64 | U64_2 | U64_3 | U64_4 => (0, u64::MAX as i128),
+        _ => return None,
+    };
+
+    Some(bounds)
+}
+
+/// Clamp each integer literal in a space-separated list to the range of the
+/// elements of `node_type`. Returns `None` if `node_type` is not an integer type.
+///
+/// Tokens that are not integer literals are left as-is so the regular parser
+/// reports them.
+pub(crate) fn saturate_integers(node_type: StandardType, input: &str) -> Option<String> {
+    let (min, max) = integer_bounds(node_type)?;
+
+    let parts: Vec<String> = input
+        .split(' ')
+        .map(|part| {
+            let parsed = match part.strip_prefix("0x") {
+                Some(hex) => i128::from_str_radix(hex, 16),
+                None => part.parse::<i128>(),
+            };
+
+            let value = match parsed {
+                Ok(value) => value,
+                Err(e) if *e.kind() == IntErrorKind::PosOverflow => max,
+                Err(e) if *e.kind() == IntErrorKind::NegOverflow => min,
+                Err(_) => return part.to_owned(),
+            };
+
+            value.clamp(min, max).to_string()
+        })
+        .collect();
+
+    Some(parts.join(" "))
+}
+
+/// Rewrite each space-separated integer literal in `input` from `radix` to
+/// decimal so the regular parser can read it. Returns the input unchanged if
+/// `node_type` is not an integer type.
+pub(crate) fn integers_from_radix(
+    node_type: StandardType,
+    input: &str,
+    radix: u32,
+) -> Result<String> {
+    if !(2..=36).contains(&radix) {
+        return Err(KbinError::InvalidRadix { radix });
+    }
+    if integer_bounds(node_type).is_none() {
+        return Ok(input.to_owned());
+    }
+
+    let parts = input
+        .split(' ')
+        .map(|part| {
+            // Range checks are left to the regular parser
+            i128::from_str_radix(part, radix)
+                .map(|value| value.to_string())
+                .context(StringParseIntSnafu {
+                    node_type: node_type.name,
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(parts.join(" "))
+}
+
 fn parse_tuple<T>(node_type: &'static str, input: &str, output: &mut [T]) -> Result<()>
 where
     T: FromStr,
@@ -36,7 +119,7 @@ where
     for (i, part) in input.split(' ').enumerate() {
         output[i] = part
             .parse::<T>()
-            .map_err(|e| Box::new(e) as Box<(dyn Error + Send + Sync + 'static)>)
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync + 'static>)
             .context(StringParseSnafu { node_type })?;
     }
 
@@ -56,7 +139,7 @@ impl FromKbinString for bool {
 
 impl FromKbinString for Ipv4Addr {
     fn from_kbin_string(input: &str) -> Result<Self> {
-        space_check(input)?;
+        space_check(StandardType::Ip4, input)?;
 
         let count = input.split('.').count();
         if count != 4 {
@@ -81,11 +164,11 @@ impl FromKbinString for Ipv4Addr {
 }
 
 macro_rules! basic_int_parse {
-    ($($type:ty),*$(,)?) => {
+    ($($type:ty => $konst:ident),*$(,)?) => {
         $(
             impl FromKbinString for $type {
                 fn from_kbin_string(input: &str) -> Result<Self> {
-                    space_check(input)?;
+                    space_check(StandardType::$konst, input)?;
 
                     if let Some(input) = input.strip_prefix("0x") {
                         <$type>::from_str_radix(input, 16)
@@ -102,11 +185,11 @@ macro_rules! basic_int_parse {
 }
 
 macro_rules! basic_float_parse {
-    ($($type:ty),*$(,)?) => {
+    ($($type:ty => $konst:ident),*$(,)?) => {
         $(
             impl FromKbinString for $type {
                 fn from_kbin_string(input: &str) -> Result<Self> {
-                    space_check(input)?;
+                    space_check(StandardType::$konst, input)?;
 
                     input
                         .parse::<$type>()
@@ -166,14 +249,14 @@ macro_rules! tuple_parse {
 }
 
 basic_int_parse! {
-    i8, u8,
-    i16, u16,
-    i32, u32,
-    i64, u64,
+    i8 => S8, u8 => U8,
+    i16 => S16, u16 => U16,
+    i32 => S32, u32 => U32,
+    i64 => S64, u64 => U64,
 }
 
 basic_float_parse! {
-    f32, f64,
+    f32 => Float, f64 => Double,
 }
 
 tuple_parse! {
@@ -191,3 +274,52 @@ tuple_parse! {
         [f64; 2, 3, 4],
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saturate() {
+        assert_eq!(
+            saturate_integers(StandardType::U8, "300").as_deref(),
+            Some("255")
+        );
+        assert_eq!(
+            saturate_integers(StandardType::S16_2, "-40000 0x7FFFFF").as_deref(),
+            Some("-32768 32767")
+        );
+        assert_eq!(
+            saturate_integers(
+                StandardType::U64,
+                "-1 99999999999999999999999999999999999999999"
+            )
+            .as_deref(),
+            Some("0 18446744073709551615")
+        );
+        assert_eq!(
+            saturate_integers(StandardType::S32, "abc").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(saturate_integers(StandardType::Float, "1.5"), None);
+    }
+
+    #[test]
+    fn unexpected_whitespace() {
+        for input in &[" 5", "5 ", "5\t"] {
+            assert!(matches!(
+                u8::from_kbin_string(input),
+                Err(KbinError::UnexpectedWhitespace { node_type: "u8" })
+            ));
+            assert!(matches!(
+                f32::from_kbin_string(input),
+                Err(KbinError::UnexpectedWhitespace { node_type: "float" })
+            ));
+        }
+        assert!(matches!(
+            Ipv4Addr::from_kbin_string("127.0.0.1 "),
+            Err(KbinError::UnexpectedWhitespace { node_type: "ip4" })
+        ));
+        assert_eq!(u8::from_kbin_string("5").unwrap(), 5);
+    }
+}
@@ -1,13 +1,24 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io::Cursor;
+use std::iter::FromIterator;
+use std::mem;
 use std::net::Ipv4Addr;
+use std::slice;
 
 use crate::error::KbinError;
 use crate::node_types::StandardType;
 use crate::types::FromKbinString;
 use crate::types::{FromKbinBytes, IntoKbinBytes};
+use crate::value::{HashValue, Value};
 
-#[derive(Clone, Debug, PartialEq)]
+/// An array of fixed size values.
+///
+/// `String`, `Binary` and `Time` values can not be arrays in kbin, and
+/// attempting to read or parse one as an array returns
+/// `KbinError::NonArrayableType`. Repeated strings are modeled as sibling nodes
+/// instead, which `Node::string_list` builds.
+#[derive(Clone, Debug)]
 pub enum ValueArray {
     S8(Vec<i8>),
     U8(Vec<u8>),
@@ -67,6 +78,12 @@ macro_rules! type_impl {
   ) => {
     pub fn from_standard_type(node_type: StandardType, input: &[u8]) -> Result<Option<Self>, KbinError> {
       let node_size = node_type.size * node_type.count;
+
+      // Types without a fixed size can not be arrays, and would divide by zero
+      if node_size == 0 {
+        return Ok(None);
+      }
+
       let len = input.len() / node_size;
 
       // Prevent reading incomplete input data
@@ -132,7 +149,7 @@ macro_rules! type_impl {
         StandardType::Attribute |
         StandardType::Binary |
         StandardType::String |
-        StandardType::Time => return Err(KbinError::InvalidState.into()),
+        StandardType::Time => return Err(KbinError::NonArrayableType { node_type }),
         $(
           StandardType::$konst => {
             let mut values = Vec::new();
@@ -189,6 +206,51 @@ macro_rules! type_impl {
         )*
       }
     }
+
+    /// Get the element at `index` as a scalar `Value`.
+    ///
+    /// Elements of multi-value types are returned whole, so an element of
+    /// `ValueArray::U8_2` is a `Value::U8_2([u8; 2])` rather than two `Value::U8`.
+    pub fn get(&self, index: usize) -> Option<Value> {
+      match self {
+        $(
+          ValueArray::$konst(values) => values.get(index).cloned().map(Value::$konst),
+        )*
+      }
+    }
+
+    fn eq_values(&self, other: &Self) -> bool {
+      match (self, other) {
+        $(
+          (ValueArray::$konst(a), ValueArray::$konst(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_value(b))
+          },
+        )*
+        _ => false,
+      }
+    }
+
+    fn hash_values<H: Hasher>(&self, state: &mut H) {
+      match self {
+        $(
+          ValueArray::$konst(values) => {
+            values.len().hash(state);
+            for value in values {
+              value.hash_value(state);
+            }
+          },
+        )*
+      }
+    }
+
+    /// Copy the first `len` elements, or all of them if there are fewer.
+    pub(crate) fn truncated(&self, len: usize) -> Self {
+      match self {
+        $(
+          ValueArray::$konst(values) => ValueArray::$konst(values.iter().take(len).cloned().collect()),
+        )*
+      }
+    }
   };
 }
 
@@ -216,6 +278,208 @@ impl ValueArray {
     }
 }
 
+/// Arrays are hashed consistently with `PartialEq`, like `Value`.
+impl Hash for ValueArray {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        self.hash_values(state);
+    }
+}
+
+/// Arrays are compared like `Value`, with floats compared by their bit patterns.
+impl PartialEq for ValueArray {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_values(other)
+    }
+}
+
+impl Eq for ValueArray {}
+
+/// Element types that can be read out of a `ValueArray` or a tuple `Value`,
+/// used by `Value::as_fixed_array` and `Value::as_array_of`.
+pub trait ArrayElement: Copy {
+    /// The array type holding elements of this type
+    const NODE_TYPE: StandardType;
+
+    fn slice(array: &ValueArray) -> Option<&[Self]>;
+
+    /// The elements of a scalar or tuple value of this element type, such as
+    /// `Value::U16` or `Value::Vu16` for `u16`.
+    fn tuple_slice(value: &Value) -> Option<&[Self]>;
+}
+
+macro_rules! from_iter_impl {
+    ($($type:ty => $konst:ident [$($tuple:ident),*]),*$(,)?) => {
+        $(
+            impl FromIterator<$type> for ValueArray {
+                fn from_iter<I>(iter: I) -> Self
+                where
+                    I: IntoIterator<Item = $type>,
+                {
+                    ValueArray::$konst(iter.into_iter().collect())
+                }
+            }
+
+            impl ArrayElement for $type {
+                const NODE_TYPE: StandardType = StandardType::$konst;
+
+                fn slice(array: &ValueArray) -> Option<&[Self]> {
+                    match array {
+                        ValueArray::$konst(values) => Some(values),
+                        _ => None,
+                    }
+                }
+
+                fn tuple_slice(value: &Value) -> Option<&[Self]> {
+                    match value {
+                        Value::$konst(value) => Some(slice::from_ref(value)),
+                        $(
+                            Value::$tuple(values) => Some(values),
+                        )*
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_iter_impl! {
+    i8 => S8 [S8_2, S8_3, S8_4, Vs8],
+    u8 => U8 [U8_2, U8_3, U8_4, Vu8],
+    i16 => S16 [S16_2, S16_3, S16_4, Vs16],
+    u16 => U16 [U16_2, U16_3, U16_4, Vu16],
+    i32 => S32 [S32_2, S32_3, S32_4],
+    u32 => U32 [U32_2, U32_3, U32_4],
+    i64 => S64 [S64_2, S64_3, S64_4],
+    u64 => U64 [U64_2, U64_3, U64_4],
+    f32 => Float [Float2, Float3, Float4],
+    f64 => Double [Double2, Double3, Double4],
+    bool => Boolean [Boolean2, Boolean3, Boolean4, Vb],
+    Ipv4Addr => Ip4 [],
+}
+
+macro_rules! into_vec_impl {
+    ($($method:ident => $type:ty, $konst:ident);*$(;)?) => {
+        impl ValueArray {
+            $(
+                #[doc = concat!(
+                    "Take the elements of a `ValueArray::", stringify!($konst), "`. Any other ",
+                    "array, including multi-value arrays, returns `KbinError::ValueTypeMismatch`.",
+                )]
+                pub fn $method(self) -> Result<Vec<$type>, KbinError> {
+                    match self {
+                        ValueArray::$konst(values) => Ok(values),
+                        values => Err(KbinError::ValueTypeMismatch {
+                            node_type: StandardType::$konst,
+                            value: Value::Array(values),
+                        }),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+into_vec_impl! {
+    into_vec_i8 => i8, S8;
+    into_vec_u8 => u8, U8;
+    into_vec_i16 => i16, S16;
+    into_vec_u16 => u16, U16;
+    into_vec_i32 => i32, S32;
+    into_vec_u32 => u32, U32;
+    into_vec_i64 => i64, S64;
+    into_vec_u64 => u64, U64;
+    into_vec_f32 => f32, Float;
+    into_vec_f64 => f64, Double;
+    into_vec_bool => bool, Boolean;
+    into_vec_ip4 => Ipv4Addr, Ip4;
+}
+
+macro_rules! numeric_values {
+    (
+        $array:expr,
+        scalar: [$($scalar:ident),*],
+        multi: [$($multi:ident),*]$(,)?
+    ) => {
+        match $array {
+            $(
+                ValueArray::$scalar(values) => {
+                    Some(Box::new(values.iter().map(|&n| n as f64)) as Box<dyn Iterator<Item = f64> + '_>)
+                },
+            )*
+            $(
+                ValueArray::$multi(values) => {
+                    Some(Box::new(values.iter().flatten().map(|&n| n as f64)) as Box<dyn Iterator<Item = f64> + '_>)
+                },
+            )*
+            _ => None,
+        }
+    };
+}
+
+impl ValueArray {
+    /// Iterate over every number in a numeric array as an `f64`, flattening the
+    /// components of multi-value elements. Returns `None` for `Ip4` and boolean
+    /// arrays.
+    fn numeric_values(&self) -> Option<Box<dyn Iterator<Item = f64> + '_>> {
+        numeric_values!(
+            self,
+            scalar: [S8, U8, S16, U16, S32, U32, S64, U64, Float, Double],
+            multi: [
+                S8_2, S8_3, S8_4, Vs8,
+                U8_2, U8_3, U8_4, Vu8,
+                S16_2, S16_3, S16_4, Vs16,
+                U16_2, U16_3, U16_4, Vu16,
+                S32_2, S32_3, S32_4,
+                U32_2, U32_3, U32_4,
+                S64_2, S64_3, S64_4,
+                U64_2, U64_3, U64_4,
+                Float2, Float3, Float4,
+                Double2, Double3, Double4
+            ],
+        )
+    }
+
+    /// Sum of every number in a numeric array. Multi-value elements such as
+    /// `U8_2` are flattened, so all of their components are included.
+    ///
+    /// Values are converted to `f64`, so 64-bit integers beyond 2^53 lose
+    /// precision. Returns `None` for `Ip4` and boolean arrays.
+    pub fn sum_f64(&self) -> Option<f64> {
+        self.numeric_values().map(|values| values.sum())
+    }
+
+    /// Smallest number in a numeric array, with the same flattening and
+    /// conversion as `sum_f64`. NaN values are ignored. Returns `None` for empty
+    /// arrays and for `Ip4` and boolean arrays.
+    pub fn min(&self) -> Option<f64> {
+        self.numeric_values()?
+            .filter(|n| !n.is_nan())
+            .fold(None, |min, n| Some(min.map_or(n, |min: f64| min.min(n))))
+    }
+
+    /// Largest number in a numeric array, see `min`.
+    pub fn max(&self) -> Option<f64> {
+        self.numeric_values()?
+            .filter(|n| !n.is_nan())
+            .fold(None, |max, n| Some(max.map_or(n, |max: f64| max.max(n))))
+    }
+
+    /// Iterate over the elements as scalar `Value`s, see `get` for the shape of
+    /// multi-value elements.
+    pub fn iter_values(&self) -> impl Iterator<Item = Value> + '_ {
+        let mut index = 0;
+
+        std::iter::from_fn(move || {
+            let value = self.get(index)?;
+            index += 1;
+
+            Some(value)
+        })
+    }
+}
+
 fn write_values<T: fmt::Display>(f: &mut fmt::Formatter, values: &[T]) -> fmt::Result {
     for (i, v) in values.iter().enumerate() {
         if i > 0 {
@@ -246,9 +510,34 @@ write_array!(write_array_4, 4);
 write_array!(write_array_8, 8);
 write_array!(write_array_16, 16);
 
+/// Write floats in the shortest form that parses back to the same value
+fn write_shortest<'a, T, I>(f: &mut fmt::Formatter, values: I) -> fmt::Result
+where
+    T: fmt::Debug + 'a,
+    I: Iterator<Item = &'a T>,
+{
+    for (i, v) in values.enumerate() {
+        if i > 0 {
+            f.write_str(" ")?;
+        }
+        fmt::Debug::fmt(v, f)?;
+    }
+    Ok(())
+}
+
+/// Like `Value`, the alternate flag (`{:#}`) writes floats in their shortest
+/// round-trip form.
 impl fmt::Display for ValueArray {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            ValueArray::Float(v) if f.alternate() => write_shortest(f, v.iter()),
+            ValueArray::Double(v) if f.alternate() => write_shortest(f, v.iter()),
+            ValueArray::Float2(v) if f.alternate() => write_shortest(f, v.iter().flatten()),
+            ValueArray::Double2(v) if f.alternate() => write_shortest(f, v.iter().flatten()),
+            ValueArray::Float3(v) if f.alternate() => write_shortest(f, v.iter().flatten()),
+            ValueArray::Double3(v) if f.alternate() => write_shortest(f, v.iter().flatten()),
+            ValueArray::Float4(v) if f.alternate() => write_shortest(f, v.iter().flatten()),
+            ValueArray::Double4(v) if f.alternate() => write_shortest(f, v.iter().flatten()),
             ValueArray::S8(v) => write_values(f, v),
             ValueArray::U8(v) => write_values(f, v),
             ValueArray::S16(v) => write_values(f, v),
@@ -334,3 +623,105 @@ impl fmt::Display for ValueArray {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iter_values() {
+        let values = ValueArray::U32(vec![1, 2, 3]);
+        let values: Vec<_> = values.iter_values().collect();
+        assert_eq!(values, [Value::U32(1), Value::U32(2), Value::U32(3)]);
+
+        let values = ValueArray::U8_2(vec![[1, 2], [3, 4]]);
+        let values: Vec<_> = values.iter_values().collect();
+        assert_eq!(values, [Value::U8_2([1, 2]), Value::U8_2([3, 4])]);
+
+        assert_eq!(ValueArray::Boolean(Vec::new()).iter_values().count(), 0);
+    }
+
+    #[test]
+    fn statistics() {
+        let values = ValueArray::S16(vec![3, -7, 10]);
+        assert_eq!(values.sum_f64(), Some(6.0));
+        assert_eq!(values.min(), Some(-7.0));
+        assert_eq!(values.max(), Some(10.0));
+
+        let values = ValueArray::U8_2(vec![[1, 9], [4, 2]]);
+        assert_eq!(values.sum_f64(), Some(16.0));
+        assert_eq!((values.min(), values.max()), (Some(1.0), Some(9.0)));
+
+        let values = ValueArray::Float(vec![f32::NAN, 1.5]);
+        assert_eq!((values.min(), values.max()), (Some(1.5), Some(1.5)));
+
+        let empty = ValueArray::U32(Vec::new());
+        assert_eq!((empty.sum_f64(), empty.min()), (Some(0.0), None));
+
+        let flags = ValueArray::Boolean(vec![true]);
+        assert_eq!(
+            (flags.sum_f64(), flags.min(), flags.max()),
+            (None, None, None)
+        );
+    }
+
+    #[test]
+    fn from_iter() {
+        let values: ValueArray = (1..=3u32).collect();
+        assert_eq!(values, ValueArray::U32(vec![1, 2, 3]));
+
+        let doubled: ValueArray = values
+            .iter_values()
+            .filter_map(|value| match value {
+                Value::U32(n) => Some(n as u64 * 2),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(doubled, ValueArray::U64(vec![2, 4, 6]));
+
+        let flags: ValueArray = vec![true, false].into_iter().collect();
+        assert_eq!(flags.standard_type(), StandardType::Boolean);
+    }
+
+    #[test]
+    fn ip4_array_round_trip() {
+        let input = br#"<root><ips __type="ip4" __count="2">1.2.3.4 5.6.7.8</ips></root>"#;
+        let expected = ValueArray::Ip4(vec![Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::new(5, 6, 7, 8)]);
+
+        let (collection, _) = crate::from_text_xml(input).unwrap();
+        let node = collection.as_node().unwrap();
+        let ips = node.get_child("ips").unwrap();
+        assert_eq!(ips.value(), Some(&Value::Array(expected.clone())));
+
+        let binary = node.to_binary().unwrap();
+        let (collection, _) = crate::from_slice(&binary).unwrap();
+        let node = collection.as_node().unwrap();
+        assert_eq!(
+            node.get_child("ips").unwrap().value(),
+            Some(&Value::Array(expected))
+        );
+
+        let text = String::from_utf8(crate::to_text_xml(&node).unwrap()).unwrap();
+        assert!(
+            text.contains(r#"__type="ip4">1.2.3.4 5.6.7.8<"#),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn into_vec() {
+        assert_eq!(ValueArray::U32(vec![1, 2]).into_vec_u32().unwrap(), [1, 2]);
+        assert_eq!(ValueArray::Float(vec![0.5]).into_vec_f32().unwrap(), [0.5]);
+        assert!(ValueArray::S8(vec![]).into_vec_i8().unwrap().is_empty());
+
+        match ValueArray::S32(vec![1]).into_vec_u32() {
+            Err(KbinError::ValueTypeMismatch { node_type, value }) => {
+                assert_eq!(node_type, StandardType::U32);
+                assert_eq!(value, Value::Array(ValueArray::S32(vec![1])));
+            },
+            result => panic!("expected type mismatch, got {:?}", result),
+        }
+        assert!(ValueArray::U32_2(vec![[1, 2]]).into_vec_u32().is_err());
+    }
+}
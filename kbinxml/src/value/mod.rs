@@ -1,19 +1,92 @@
 use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io::Cursor;
+use std::mem;
 use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use rustc_hex::FromHex;
 use snafu::ResultExt;
 
 use crate::error::{HexSnafu, KbinError, Result};
 use crate::node_types::StandardType;
-use crate::types::{FromKbinBytes, FromKbinString, IntoKbinBytes};
+use crate::types::{integers_from_radix, FromKbinBytes, FromKbinString, IntoKbinBytes};
 
 mod array;
 
-pub use self::array::ValueArray;
+pub use self::array::{ArrayElement, ValueArray};
+
+/// Hashing and comparison of the contents of a `Value`, with floats compared by
+/// their bit patterns so that equality is reflexive.
+pub(crate) trait HashValue {
+    fn hash_value<H: Hasher>(&self, state: &mut H);
+
+    fn eq_value(&self, other: &Self) -> bool;
+}
+
+macro_rules! hash_value_impl {
+    ($($type:ty),*) => {
+        $(
+            impl HashValue for $type {
+                #[inline]
+                fn hash_value<H: Hasher>(&self, state: &mut H) {
+                    self.hash(state);
+                }
+
+                #[inline]
+                fn eq_value(&self, other: &Self) -> bool {
+                    self == other
+                }
+            }
+        )*
+    };
+}
+
+hash_value_impl!(i8, u8, i16, u16, i32, u32, i64, u64, bool, Ipv4Addr, String);
+
+// Floats are hashed and compared by their bit patterns, with `-0.0` treated as
+// `0.0`. A NaN is equal to a NaN with the same bits.
+impl HashValue for f32 {
+    #[inline]
+    fn hash_value<H: Hasher>(&self, state: &mut H) {
+        let n = if *self == 0.0 { 0.0f32 } else { *self };
+        n.to_bits().hash(state);
+    }
+
+    #[inline]
+    fn eq_value(&self, other: &Self) -> bool {
+        self == other || self.to_bits() == other.to_bits()
+    }
+}
+
+impl HashValue for f64 {
+    #[inline]
+    fn hash_value<H: Hasher>(&self, state: &mut H) {
+        let n = if *self == 0.0 { 0.0f64 } else { *self };
+        n.to_bits().hash(state);
+    }
+
+    #[inline]
+    fn eq_value(&self, other: &Self) -> bool {
+        self == other || self.to_bits() == other.to_bits()
+    }
+}
+
+impl<T: HashValue, const N: usize> HashValue for [T; N] {
+    #[inline]
+    fn hash_value<H: Hasher>(&self, state: &mut H) {
+        for v in self {
+            v.hash_value(state);
+        }
+    }
+
+    #[inline]
+    fn eq_value(&self, other: &Self) -> bool {
+        self.iter().zip(other).all(|(a, b)| a.eq_value(b))
+    }
+}
 
 macro_rules! construct_types {
     (
@@ -21,7 +94,7 @@ macro_rules! construct_types {
             ($konst:ident, $($value_type:tt)*);
         )+
     ) => {
-        #[derive(Clone, PartialEq)]
+        #[derive(Clone)]
         pub enum Value {
             $(
                 $konst($($value_type)*),
@@ -86,6 +159,45 @@ macro_rules! construct_types {
                 }
             }
         }
+
+        /// Values are hashed consistently with `PartialEq`, with floats hashed by
+        /// their bit patterns and `-0.0` hashed like `0.0`.
+        impl Hash for Value {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                mem::discriminant(self).hash(state);
+
+                match self {
+                    $(
+                        Value::$konst(v) => v.hash_value(state),
+                    )+
+                    Value::Binary(v) => v.hash(state),
+                    Value::Time(v) => v.hash(state),
+                    Value::Attribute(v) => v.hash(state),
+                    Value::Array(v) => v.hash(state),
+                }
+            }
+        }
+
+        /// Values are equal if they have the same type and contents. Floats are
+        /// compared by their bit patterns rather than numerically, except that
+        /// `-0.0` equals `0.0`, so a NaN equals a NaN with the same bits.
+        impl PartialEq for Value {
+            fn eq(&self, other: &Self) -> bool {
+                match (self, other) {
+                    $(
+                        (Value::$konst(a), Value::$konst(b)) => a.eq_value(b),
+                    )+
+                    (Value::Binary(a), Value::Binary(b)) => a == b,
+                    (Value::Time(a), Value::Time(b)) => a == b,
+                    (Value::Attribute(a), Value::Attribute(b)) => a == b,
+                    (Value::Array(a), Value::Array(b)) => a == b,
+                    _ => false,
+                }
+            }
+        }
+
+        /// `Value` is `Eq` so that it can be used as a `HashMap` or `HashSet` key.
+        impl Eq for Value {}
     }
 }
 
@@ -103,7 +215,7 @@ macro_rules! tuple {
             if is_array {
                 let value = match ValueArray::from_standard_type(node_type, input)? {
                     Some(value) => value,
-                    None => return Err(KbinError::InvalidState),
+                    None => return Err(KbinError::NonArrayableType { node_type }),
                 };
                 debug!(
                     "Value::from_standard_type({:?}) input: 0x{:02x?} => {:?}",
@@ -159,6 +271,11 @@ macro_rules! tuple {
             Ok(Some(value))
         }
 
+        /// Parse a value from its text XML representation.
+        ///
+        /// Non-finite floats are written as `NaN`, `inf` and `-inf` and are
+        /// parsed back case-insensitively, so they round-trip through text XML.
+        /// The sign and payload of a NaN are not preserved.
         pub fn from_string(
             node_type: StandardType,
             input: &str,
@@ -175,7 +292,7 @@ macro_rules! tuple {
 
             if is_array {
                 let value = match node_type.count {
-                    0 => return Err(KbinError::InvalidState.into()),
+                    0 => return Err(KbinError::NonArrayableType { node_type }),
                     count => Value::Array(ValueArray::from_string(node_type, count, input, arr_count)?),
                 };
                 debug!("Value::from_string({:?}) input: {:?} => {:?}", node_type, input, value);
@@ -264,6 +381,21 @@ impl Value {
         Double2, Double3, Double4,
     }
 
+    /// Parse a value from text like `from_string`, reading integers in the given
+    /// `radix` without a prefix, such as `ff` in base 16. `radix` must be between
+    /// 2 and 36. Types other than integers are parsed as by `from_string`.
+    pub fn from_string_radix(
+        node_type: StandardType,
+        input: &str,
+        radix: u32,
+        is_array: bool,
+        arr_count: usize,
+    ) -> Result<Value> {
+        let input = integers_from_radix(node_type, input, radix)?;
+
+        Value::from_string(node_type, &input, is_array, arr_count)
+    }
+
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
         let mut output = Vec::new();
         self.to_bytes_inner(&mut output)?;
@@ -356,6 +488,64 @@ impl Value {
         }
     }
 
+    /// Widen any integer scalar to `u64`.
+    ///
+    /// Signed values are accepted if they are not negative, otherwise
+    /// `KbinError::ValueOutOfRange` is returned. Non-integer values return
+    /// `KbinError::ValueTypeMismatch`.
+    pub fn coerce_to_u64(&self) -> Result<u64> {
+        let value = match *self {
+            Value::U8(n) => Some(u64::from(n)),
+            Value::U16(n) => Some(u64::from(n)),
+            Value::U32(n) => Some(u64::from(n)),
+            Value::U64(n) => Some(n),
+            Value::S8(n) => u64::try_from(n).ok(),
+            Value::S16(n) => u64::try_from(n).ok(),
+            Value::S32(n) => u64::try_from(n).ok(),
+            Value::S64(n) => u64::try_from(n).ok(),
+            ref value => {
+                return Err(KbinError::ValueTypeMismatch {
+                    node_type: StandardType::U64,
+                    value: value.clone(),
+                })
+            },
+        };
+
+        value.ok_or_else(|| KbinError::ValueOutOfRange {
+            node_type: StandardType::U64,
+            value: self.clone(),
+        })
+    }
+
+    /// Widen any integer scalar to `i64`.
+    ///
+    /// A `U64` value greater than `i64::MAX` does not fit and returns
+    /// `KbinError::ValueOutOfRange`. Non-integer values return
+    /// `KbinError::ValueTypeMismatch`.
+    pub fn coerce_to_i64(&self) -> Result<i64> {
+        let value = match *self {
+            Value::S8(n) => Some(i64::from(n)),
+            Value::S16(n) => Some(i64::from(n)),
+            Value::S32(n) => Some(i64::from(n)),
+            Value::S64(n) => Some(n),
+            Value::U8(n) => Some(i64::from(n)),
+            Value::U16(n) => Some(i64::from(n)),
+            Value::U32(n) => Some(i64::from(n)),
+            Value::U64(n) => i64::try_from(n).ok(),
+            ref value => {
+                return Err(KbinError::ValueTypeMismatch {
+                    node_type: StandardType::S64,
+                    value: value.clone(),
+                })
+            },
+        };
+
+        value.ok_or_else(|| KbinError::ValueOutOfRange {
+            node_type: StandardType::S64,
+            value: self.clone(),
+        })
+    }
+
     pub fn as_slice(&self) -> Result<&[u8]> {
         match self {
             Value::Binary(ref data) => Ok(data),
@@ -396,6 +586,20 @@ impl Value {
         }
     }
 
+    /// Returns the inner string of either a `String` or an `Attribute` value.
+    pub fn as_string_any(&self) -> Option<&str> {
+        match self {
+            Value::String(ref s) | Value::Attribute(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this is a `Value::Attribute`.
+    #[inline]
+    pub fn is_attribute_value(&self) -> bool {
+        matches!(self, Value::Attribute(_))
+    }
+
     pub fn as_binary(&self) -> Result<&[u8]> {
         match self {
             Value::Binary(ref data) => Ok(data),
@@ -415,6 +619,140 @@ impl Value {
         }
     }
 
+    /// The time since the Unix epoch of a `Time` value, or `None` for other
+    /// values.
+    pub fn time_duration_since_epoch(&self) -> Option<Duration> {
+        match *self {
+            Value::Time(seconds) => Some(Duration::from_secs(seconds as u64)),
+            _ => None,
+        }
+    }
+
+    /// Create a `Time` value from a `SystemTime`, truncated to whole seconds.
+    ///
+    /// Returns an error if `time` is before the Unix epoch or too far after it
+    /// to fit in the `u32` seconds of a `Time` value.
+    pub fn time_from_system_time(time: SystemTime) -> Result<Value> {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| KbinError::TimeBeforeEpoch)?
+            .as_secs();
+
+        u32::try_from(seconds)
+            .map(Value::Time)
+            .map_err(|_| KbinError::TimeOutOfRange { seconds })
+    }
+
+    /// Copy the elements of an array value into a fixed size array, such as
+    /// `[i32; 3]` from a `ValueArray::S32` with 3 elements.
+    ///
+    /// Returns an error if this is not an array, if its elements are not of type
+    /// `T` or if it does not have exactly `N` elements.
+    pub fn as_fixed_array<T, const N: usize>(&self) -> Result<[T; N]>
+    where
+        T: ArrayElement,
+    {
+        let array = self.as_array()?;
+        let values = T::slice(array).ok_or(KbinError::TypeMismatch {
+            expected: T::NODE_TYPE,
+            found: array.standard_type(),
+        })?;
+
+        <[T; N]>::try_from(values).map_err(|_| KbinError::SizeMismatch {
+            node_type: T::NODE_TYPE.name,
+            expected: N,
+            actual: values.len(),
+        })
+    }
+
+    /// Copy the elements of a scalar, tuple or array value into a fixed size
+    /// array, such as `[u16; 8]` from a `Value::Vu16` or `[i8; 16]` from a
+    /// `Value::Vs8`.
+    ///
+    /// Array values are handled as by `as_fixed_array`. Returns an error if the
+    /// elements are not of type `T` or if there are not exactly `N` of them.
+    pub fn as_array_of<const N: usize, T>(&self) -> Result<[T; N]>
+    where
+        T: ArrayElement,
+    {
+        if self.is_array() {
+            return self.as_fixed_array();
+        }
+
+        let values = T::tuple_slice(self).ok_or(KbinError::TypeMismatch {
+            expected: T::NODE_TYPE,
+            found: self.standard_type(),
+        })?;
+
+        <[T; N]>::try_from(values).map_err(|_| KbinError::SizeMismatch {
+            node_type: self.standard_type().name,
+            expected: N,
+            actual: values.len(),
+        })
+    }
+
+    /// Number of bytes this value occupies in the data buffer when written,
+    /// including the length prefix and padding of sized values.
+    ///
+    /// `String` and `Attribute` sizes assume a single byte per UTF-8 byte, which is
+    /// exact for UTF-8 and ASCII documents. 1 and 2 byte values are packed into
+    /// shared 4 byte slots by the writer, so their unpadded size is returned and
+    /// the sum over a document can be short by up to 3 bytes per slot left open.
+    pub fn encoded_size(&self) -> usize {
+        fn padded(len: usize) -> usize {
+            (len + 3) & !3
+        }
+
+        match self {
+            Value::String(text) | Value::Attribute(text) => 4 + padded(text.len() + 1),
+            Value::Binary(data) => 4 + padded(data.len()),
+            Value::Array(values) => {
+                let node_type = values.standard_type();
+                4 + padded(values.len() * node_type.size * node_type.count)
+            },
+            value => {
+                let node_type = value.standard_type();
+                match node_type.size * node_type.count {
+                    size @ (1 | 2) => size,
+                    size => padded(size),
+                }
+            },
+        }
+    }
+
+    /// Returns `true` if this is a `Value::Array`.
+    #[inline]
+    pub fn is_array(&self) -> bool {
+        matches!(self, Value::Array(_))
+    }
+
+    /// Returns the element type of a `Value::Array`, or `None` for scalar values.
+    pub fn array_element_type(&self) -> Option<StandardType> {
+        match self {
+            Value::Array(ref values) => Some(values.standard_type()),
+            _ => None,
+        }
+    }
+
+    /// Display this value with at most `max_elements` elements of an array, or
+    /// bytes of a `Binary` value, followed by the number of elements left out.
+    /// Other values are displayed in full.
+    pub fn to_string_truncated(&self, max_elements: usize) -> String {
+        match self {
+            Value::Array(values) if values.len() > max_elements => format!(
+                "{} … (+{} more)",
+                values.truncated(max_elements),
+                values.len() - max_elements
+            ),
+            Value::Binary(data) if data.len() > max_elements => format!(
+                "{} … (+{} more)",
+                Value::Binary(data[..max_elements].to_vec()),
+                data.len() - max_elements
+            ),
+            value => value.to_string(),
+        }
+    }
+
     pub fn into_binary(self) -> Result<Vec<u8>> {
         match self {
             Value::Binary(data) => Ok(data),
@@ -455,13 +793,11 @@ impl TryFrom<&Value> for Vec<u8> {
     fn try_from(value: &Value) -> Result<Self> {
         match value {
             Value::Binary(ref data) => Ok(data.to_vec()),
-            Value::Array(ref values) => match values.clone() {
-                ValueArray::U8(values) => Ok(values),
-                values => Err(KbinError::ValueTypeMismatch {
-                    node_type: StandardType::U8,
-                    value: Value::Array(values),
-                }),
-            },
+            Value::Array(ValueArray::U8(ref values)) => Ok(values.clone()),
+            value @ Value::Array(_) => Err(KbinError::ValueTypeMismatch {
+                node_type: StandardType::U8,
+                value: value.clone(),
+            }),
             value => Err(KbinError::ValueTypeMismatch {
                 node_type: StandardType::Binary,
                 value: value.clone(),
@@ -547,6 +883,9 @@ impl fmt::Debug for Value {
     }
 }
 
+/// Formats a value as it is written in text XML. The alternate flag (`{:#}`)
+/// writes floats in the shortest form that parses back to the same value,
+/// instead of with 6 decimal places.
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         macro_rules! display_value {
@@ -589,6 +928,8 @@ impl fmt::Display for Value {
                         }
                         Ok(())
                     },
+                    Value::Float(n) if f.alternate() => write!(f, "{:?}", n),
+                    Value::Double(n) if f.alternate() => write!(f, "{:?}", n),
                     Value::Float(n) => write!(f, "{:.6}", n),
                     Value::Double(n) => write!(f, "{:.6}", n),
                     Value::Boolean(b) => match b {
@@ -677,3 +1018,286 @@ construct_types! {
     (Boolean4, [bool; 4]);
     (Vb,       [bool; 16]);
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use super::*;
+
+    use byteorder::{BigEndian, ByteOrder};
+    use test::{black_box, Bencher};
+
+    use crate::node::Node;
+
+    fn data_buffer_len(node: &Node) -> usize {
+        let output = node.to_binary().unwrap();
+        let len_node = BigEndian::read_u32(&output[4..8]) as usize;
+
+        BigEndian::read_u32(&output[8 + len_node..]) as usize
+    }
+
+    #[test]
+    fn encoded_size_matches_writer() {
+        let values = vec![
+            Value::String("hello".into()),
+            Value::String(String::new()),
+            Value::Binary(vec![1, 2, 3, 4, 5]),
+            Value::U8_3([1, 2, 3]),
+            Value::S32(-1),
+            Value::U64(1),
+            Value::Float3([1.0, 2.0, 3.0]),
+            Value::Array(ValueArray::U16(vec![1, 2, 3])),
+            Value::Array(ValueArray::S64_2(vec![[1, 2]])),
+        ];
+
+        for value in values {
+            let node = Node::with_value("v", value.clone());
+            assert_eq!(value.encoded_size(), data_buffer_len(&node), "{:?}", value);
+        }
+
+        let node = Node::with_attrs("n", &[("a", "attr")]);
+        let size = Value::Attribute("attr".into()).encoded_size();
+        assert_eq!(size, data_buffer_len(&node));
+    }
+
+    #[test]
+    fn to_string_truncated() {
+        let value = Value::Array(ValueArray::U16((0..1000).collect()));
+        assert_eq!(value.to_string_truncated(3), "0 1 2 … (+997 more)");
+        assert_eq!(value.to_string_truncated(1000), value.to_string());
+
+        let value = Value::Array(ValueArray::U8_2(vec![[1, 2], [3, 4], [5, 6]]));
+        assert_eq!(value.to_string_truncated(2), "1 2 3 4 … (+1 more)");
+
+        let value = Value::Binary(vec![0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(value.to_string_truncated(2), "dead … (+2 more)");
+        assert_eq!(Value::S32(123456).to_string_truncated(0), "123456");
+    }
+
+    #[test]
+    fn time_system_time() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_500_999);
+        let value = Value::time_from_system_time(time).unwrap();
+        assert_eq!(value, Value::Time(1_500));
+        assert_eq!(
+            value.time_duration_since_epoch(),
+            Some(Duration::from_secs(1_500))
+        );
+        assert_eq!(Value::U32(1).time_duration_since_epoch(), None);
+
+        let max = UNIX_EPOCH + Duration::from_secs(u32::MAX as u64);
+        assert_eq!(
+            Value::time_from_system_time(max).unwrap(),
+            Value::Time(u32::MAX)
+        );
+        assert!(matches!(
+            Value::time_from_system_time(max + Duration::from_secs(1)),
+            Err(KbinError::TimeOutOfRange { seconds }) if seconds == u32::MAX as u64 + 1
+        ));
+        assert!(matches!(
+            Value::time_from_system_time(UNIX_EPOCH - Duration::from_secs(1)),
+            Err(KbinError::TimeBeforeEpoch)
+        ));
+    }
+
+    #[test]
+    fn as_fixed_array() {
+        let value = Value::Array(ValueArray::S32(vec![1, -2, 3]));
+        assert_eq!(value.as_fixed_array::<i32, 3>().unwrap(), [1, -2, 3]);
+
+        let value = Value::Array(ValueArray::Float(vec![0.5, 1.5]));
+        assert_eq!(value.as_fixed_array::<f32, 2>().unwrap(), [0.5, 1.5]);
+
+        let value = Value::Array(ValueArray::U8(vec![1, 2, 3, 4]));
+        assert_eq!(value.as_fixed_array::<u8, 4>().unwrap(), [1, 2, 3, 4]);
+        assert!(matches!(
+            value.as_fixed_array::<u8, 3>(),
+            Err(KbinError::SizeMismatch {
+                expected: 3,
+                actual: 4,
+                ..
+            })
+        ));
+        assert!(matches!(
+            value.as_fixed_array::<i8, 4>(),
+            Err(KbinError::TypeMismatch {
+                expected: StandardType::S8,
+                found: StandardType::U8,
+            })
+        ));
+        assert!(matches!(
+            Value::U8(1).as_fixed_array::<u8, 1>(),
+            Err(KbinError::ExpectedValueArray { .. })
+        ));
+    }
+
+    #[test]
+    fn as_array_of() {
+        let values = [1, 2, 3, 4, 5, 6, 7, u16::MAX];
+        let value = Value::Vu16(values);
+        assert_eq!(value.as_array_of::<8, u16>().unwrap(), values);
+        assert!(matches!(
+            value.as_array_of::<4, u16>(),
+            Err(KbinError::SizeMismatch {
+                expected: 4,
+                actual: 8,
+                ..
+            })
+        ));
+        assert!(matches!(
+            value.as_array_of::<8, i16>(),
+            Err(KbinError::TypeMismatch {
+                expected: StandardType::S16,
+                found: StandardType::Vu16,
+            })
+        ));
+
+        let mut values = [0i8; 16];
+        values.iter_mut().zip(-8..).for_each(|(v, n)| *v = n);
+        let value = Value::Vs8(values);
+        assert_eq!(value.as_array_of::<16, i8>().unwrap(), values);
+
+        assert_eq!(Value::U8(7).as_array_of::<1, u8>().unwrap(), [7]);
+
+        let value = Value::Array(ValueArray::U16(values.iter().map(|&n| n as u16).collect()));
+        assert_eq!(value.as_array_of::<16, u16>().unwrap()[8], 0);
+    }
+
+    #[test]
+    fn from_string_radix() {
+        let parse =
+            |node_type, input, radix| Value::from_string_radix(node_type, input, radix, false, 1);
+
+        assert_eq!(parse(StandardType::U8, "ff", 16).unwrap(), Value::U8(0xff));
+        assert_eq!(
+            parse(StandardType::S16, "-7f", 16).unwrap(),
+            Value::S16(-0x7f)
+        );
+        assert_eq!(
+            parse(StandardType::U8_2, "101 11", 2).unwrap(),
+            Value::U8_2([5, 3])
+        );
+        assert_eq!(parse(StandardType::U8, "10", 10).unwrap(), Value::U8(10));
+        assert_eq!(
+            parse(StandardType::String, "ff", 16).unwrap(),
+            Value::String("ff".into())
+        );
+
+        let array = Value::from_string_radix(StandardType::U16, "a b c", 16, true, 3).unwrap();
+        assert_eq!(array, Value::Array(ValueArray::U16(vec![10, 11, 12])));
+
+        assert!(parse(StandardType::U8, "100", 16).is_err());
+        assert!(parse(StandardType::U8, "fg", 16).is_err());
+        assert!(matches!(
+            parse(StandardType::U8, "1", 37),
+            Err(KbinError::InvalidRadix { radix: 37 })
+        ));
+    }
+
+    #[test]
+    fn non_finite_float_round_trip() {
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("nan", Value::Float(f32::NAN)),
+            Node::with_value("inf", Value::Double(f64::INFINITY)),
+            Node::with_value("neg_inf", Value::Float(f32::NEG_INFINITY)),
+            Node::with_value("vec", Value::Double2([f64::NEG_INFINITY, f64::NAN])),
+        ]);
+
+        let text = crate::to_text_xml(&node).unwrap();
+        let (collection, _) = crate::from_text_xml(&text).unwrap();
+        let parsed = collection.as_node().unwrap();
+
+        let float = |key| match parsed.get_child(key).and_then(Node::value) {
+            Some(Value::Float(n)) => *n as f64,
+            Some(Value::Double(n)) => *n,
+            value => panic!("unexpected value: {:?}", value),
+        };
+        assert!(float("nan").is_nan());
+        assert_eq!(float("inf"), f64::INFINITY);
+        assert_eq!(float("neg_inf"), f64::NEG_INFINITY);
+
+        match parsed.get_child("vec").and_then(Node::value) {
+            Some(Value::Double2([a, b])) => assert!(*a == f64::NEG_INFINITY && b.is_nan()),
+            value => panic!("unexpected value: {:?}", value),
+        }
+
+        for input in &["nan", "NaN", "inf", "-Infinity"] {
+            Value::from_string(StandardType::Double, input, false, 1).unwrap();
+        }
+    }
+
+    #[test]
+    fn encoded_size_packed() {
+        let values = [Value::U8(1), Value::S8(2), Value::U16(3)];
+        let nodes: Vec<_> = values
+            .iter()
+            .map(|value| Node::with_value("v", value.clone()))
+            .collect();
+        let node = Node::with_nodes("n", nodes);
+
+        let size: usize = values.iter().map(Value::encoded_size).sum();
+        assert_eq!(size, 4);
+        assert_eq!(data_buffer_len(&node), 8);
+
+        let nodes = vec![
+            Node::with_value("a", Value::U16(1)),
+            Node::with_value("b", Value::U16(2)),
+        ];
+        assert_eq!(data_buffer_len(&Node::with_nodes("n", nodes)), 4);
+    }
+
+    #[test]
+    fn hash() {
+        use std::collections::HashMap;
+
+        let values = [
+            Value::U32(1),
+            Value::S32(1),
+            Value::U32(1),
+            Value::Float(0.0),
+            Value::Float(-0.0),
+            Value::Double2([1.5, -0.0]),
+            Value::Double2([1.5, 0.0]),
+            Value::Array(ValueArray::U8(vec![1, 2])),
+            Value::Array(ValueArray::S8(vec![1, 2])),
+            Value::Array(ValueArray::U8(vec![1, 2])),
+        ];
+
+        let mut counts = HashMap::new();
+        for value in &values {
+            *counts.entry(value).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts.len(), 6);
+        assert_eq!(counts[&Value::U32(1)], 2);
+        assert_eq!(counts[&Value::S32(1)], 1);
+        assert_eq!(counts[&Value::Float(0.0)], 2);
+        assert_eq!(counts[&Value::Double2([1.5, 0.0])], 2);
+        assert_eq!(counts[&Value::Array(ValueArray::U8(vec![1, 2]))], 2);
+
+        let nan = Value::Float(f32::NAN);
+        assert_eq!(nan, nan.clone());
+        assert_ne!(nan, Value::Float(-f32::NAN));
+        assert_eq!(
+            Value::Array(ValueArray::Double(vec![f64::NAN, -0.0])),
+            Value::Array(ValueArray::Double(vec![f64::NAN, 0.0]))
+        );
+
+        let mut counts = HashMap::new();
+        for _ in 0..2 {
+            *counts.entry(nan.clone()).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&nan], 2);
+    }
+
+    #[bench]
+    fn bench_u8_array_to_vec(b: &mut Bencher) {
+        let value = Value::Array(ValueArray::U8(vec![0xAA; 1 << 20]));
+
+        b.iter(|| {
+            let bytes = Vec::<u8>::try_from(black_box(&value)).unwrap();
+            black_box(bytes);
+        });
+    }
+}
@@ -6,15 +6,18 @@ use snafu::{ResultExt, Snafu};
 use crate::byte_buffer::{ByteBufferError, ByteBufferWrite};
 use crate::compression_type::CompressionType;
 use crate::encoding_type::{EncodingError, EncodingType};
-use crate::node::{Node, NodeCollection};
+use crate::node::{Key, Node, NodeCollection, NodeData, NodeDefinition};
 use crate::node_types::StandardType;
-use crate::options::Options;
+use crate::options::{BufferLayout, Options};
+#[cfg(feature = "rayon")]
+use crate::parallel::ParallelWriteable;
 use crate::sixbit::{Sixbit, SixbitError};
 use crate::value::Value;
 
 use super::{ARRAY_MASK, SIGNATURE};
 
 #[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
 pub enum WriterError {
     #[snafu(display("Failed to write signature to header"))]
     Signature { source: io::Error },
@@ -113,6 +116,29 @@ pub enum WriterError {
 
     #[snafu(display("Attempted to write node definition without value data"))]
     NoNodeValue,
+
+    #[snafu(display(
+        "Mismatched binary node length and size attribute value (value length: {}, size attribute: {})",
+        len,
+        size
+    ))]
+    MismatchedBinaryNodeLength { len: usize, size: String },
+
+    #[snafu(display("Invalid data buffer alignment: {}", alignment))]
+    InvalidAlignment { alignment: u64 },
+
+    #[snafu(display("Invalid node or attribute key: {:?}", key))]
+    InvalidKey { key: String },
+
+    #[snafu(display(
+        "Mismatched array flag for node type {} value (is_array: {})",
+        node_type,
+        is_array
+    ))]
+    ArrayFlagMismatch {
+        node_type: StandardType,
+        is_array: bool,
+    },
 }
 
 fn write_value(
@@ -145,7 +171,10 @@ fn write_value(
         },
         Value::Array(values) => {
             if !is_array {
-                panic!("Attempted to write value array but was not marked as array");
+                return Err(WriterError::ArrayFlagMismatch {
+                    node_type,
+                    is_array,
+                });
             }
 
             let total_size = values.len() * node_type.count * node_type.size;
@@ -170,7 +199,10 @@ fn write_value(
         },
         value => {
             if is_array {
-                panic!("Attempted to write non-array value but was marked as array");
+                return Err(WriterError::ArrayFlagMismatch {
+                    node_type,
+                    is_array,
+                });
             }
 
             let data = value.to_bytes().context(ValueEncodeSnafu { node_type })?;
@@ -183,6 +215,28 @@ fn write_value(
     Ok(())
 }
 
+/// In strict mode, check a `__size` attribute against the length of the `Binary`
+/// value it describes, like `TextXmlReader` does when reading.
+fn check_binary_size(len: usize, size: &str) -> Result<(), WriterError> {
+    match size.parse::<usize>() {
+        Ok(size) if size == len => Ok(()),
+        _ => Err(WriterError::MismatchedBinaryNodeLength {
+            len,
+            size: size.to_owned(),
+        }),
+    }
+}
+
+/// Callback invoked by `Writer` for each node as it is written, with the node's
+/// key and the current offset into the data buffer.
+///
+/// The offset is relative to the start of the data buffer, after its length
+/// field, and is taken after the node's key is written but before its value and
+/// attributes are. For nodes with a value this is where the value's data starts,
+/// except for 1 and 2 byte values, which may be packed into an earlier 4 byte
+/// slot.
+pub type NodeObserver = Box<dyn FnMut(&str, u64) + Send>;
+
 pub trait Writeable {
     fn write_node(
         &self,
@@ -190,6 +244,53 @@ pub trait Writeable {
         node_buf: &mut ByteBufferWrite,
         data_buf: &mut ByteBufferWrite,
     ) -> Result<(), WriterError>;
+
+    /// Write like `write_node`, invoking `observer` for each node written. The
+    /// default implementation ignores `observer`.
+    fn write_node_observed(
+        &self,
+        options: &Options,
+        node_buf: &mut ByteBufferWrite,
+        data_buf: &mut ByteBufferWrite,
+        observer: &mut Option<NodeObserver>,
+    ) -> Result<(), WriterError> {
+        let _ = observer;
+        self.write_node(options, node_buf, data_buf)
+    }
+
+    /// Check that every node and attribute key can be written, so that invalid
+    /// keys are reported before any output is produced. Used by `Writer` in
+    /// strict mode. The default implementation accepts every key.
+    fn validate_keys(&self, _options: &Options) -> Result<(), WriterError> {
+        Ok(())
+    }
+}
+
+/// Check that `key` is not empty and, for compressed output, only has sixbit
+/// characters.
+fn check_key(options: &Options, key: &str) -> Result<(), WriterError> {
+    let valid = match options.compression {
+        CompressionType::Compressed => !key.is_empty() && Sixbit::is_valid(key),
+        CompressionType::Uncompressed => !key.is_empty(),
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(WriterError::InvalidKey {
+            key: key.to_owned(),
+        })
+    }
+}
+
+fn check_definition_key(options: &Options, definition: &NodeDefinition) -> Result<(), WriterError> {
+    let node_type = definition.node_type;
+    let key = definition
+        .key()
+        .context(DefinitionKeySnafu { node_type })?
+        .ok_or(WriterError::NoNodeKey)?;
+
+    check_key(options, &key)
 }
 
 impl Writeable for NodeCollection {
@@ -199,109 +300,179 @@ impl Writeable for NodeCollection {
         node_buf: &mut ByteBufferWrite,
         data_buf: &mut ByteBufferWrite,
     ) -> Result<(), WriterError> {
-        let (node_type, is_array) = self.base().node_type_tuple();
-        let array_mask = if is_array { ARRAY_MASK } else { 0 };
-        let name = self
-            .base()
-            .key()
-            .context(DefinitionValueSnafu { node_type })?
-            .ok_or(WriterError::NoNodeKey)?;
-
-        debug!("NodeCollection write_node => name: {}, type: {:?}, type_size: {}, type_count: {}, is_array: {}",
-            name,
-            node_type,
-            node_type.size,
-            node_type.count,
-            is_array);
-
-        node_buf
-            .write_u8(node_type as u8 | array_mask)
-            .context(DataWriteSnafu { node_type })?;
+        self.write_node_observed(options, node_buf, data_buf, &mut None)
+    }
 
-        match options.compression {
-            CompressionType::Compressed => {
-                Sixbit::pack(&mut **node_buf, &name).context(NodeSixbitNameSnafu)?
-            },
-            CompressionType::Uncompressed => {
-                let data = options.encoding.encode_bytes(&name).context(
-                    NodeUncompressedNameEncodeSnafu {
-                        encoding: options.encoding,
-                    },
-                )?;
-                let len = (data.len() - 1) as u8;
-                node_buf
-                    .write_u8(len | ARRAY_MASK)
-                    .context(NodeUncompressedNameLengthSnafu)?;
-                node_buf
-                    .write_all(&data)
-                    .context(NodeUncompressedNameDataSnafu)?;
-            },
-        };
+    fn write_node_observed(
+        &self,
+        options: &Options,
+        node_buf: &mut ByteBufferWrite,
+        data_buf: &mut ByteBufferWrite,
+        observer: &mut Option<NodeObserver>,
+    ) -> Result<(), WriterError> {
+        write_collection_start(self, options, node_buf, data_buf, observer)?;
 
-        if node_type != StandardType::NodeStart {
-            let value = self
-                .base()
-                .value()
-                .context(DefinitionValueSnafu { node_type })?;
-            write_value(options, data_buf, node_type, is_array, &value)?;
+        for child in self.children() {
+            child.write_node_observed(options, node_buf, data_buf, observer)?;
         }
 
-        for attr in self.attributes() {
-            let node_type = StandardType::Attribute;
-            let key = attr
-                .key()
-                .context(DefinitionKeySnafu { node_type })?
-                .ok_or(WriterError::NoNodeKey)?;
-            let value = attr.value_bytes().ok_or(WriterError::NoNodeValue)?;
+        write_node_end(node_buf)
+    }
 
-            trace!(
-                "NodeCollection write_node => attr: {}, value: 0x{:02x?}",
-                key,
-                value
-            );
+    fn validate_keys(&self, options: &Options) -> Result<(), WriterError> {
+        let mut stack = vec![self];
 
-            data_buf
-                .buf_write(value)
-                .context(DataBufferSnafu { node_type })?;
+        while let Some(collection) = stack.pop() {
+            check_definition_key(options, collection.base())?;
+            for attribute in collection.attributes() {
+                check_definition_key(options, attribute)?;
+            }
+            stack.extend(collection.children());
+        }
 
-            node_buf
-                .write_u8(StandardType::Attribute as u8)
-                .context(DataWriteSnafu { node_type })?;
+        Ok(())
+    }
+}
 
-            match options.compression {
-                CompressionType::Compressed => {
-                    Sixbit::pack(&mut **node_buf, &key).context(NodeSixbitNameSnafu)?
+/// Write a node or attribute key with the compression and encoding from `options`.
+fn write_key(
+    options: &Options,
+    node_buf: &mut ByteBufferWrite,
+    key: &str,
+) -> Result<(), WriterError> {
+    match options.compression {
+        CompressionType::Compressed => {
+            Sixbit::pack(&mut **node_buf, key).context(NodeSixbitNameSnafu)?
+        },
+        CompressionType::Uncompressed => {
+            let data = options.encoding.encode_bytes_raw(key).context(
+                NodeUncompressedNameEncodeSnafu {
+                    encoding: options.encoding,
                 },
-                CompressionType::Uncompressed => {
-                    let data = options.encoding.encode_bytes(&key).context(
-                        NodeUncompressedNameEncodeSnafu {
-                            encoding: options.encoding,
-                        },
-                    )?;
-                    let len = (data.len() - 1) as u8;
-                    node_buf
-                        .write_u8(len | ARRAY_MASK)
-                        .context(NodeUncompressedNameLengthSnafu)?;
-                    node_buf
-                        .write_all(&data)
-                        .context(NodeUncompressedNameDataSnafu)?;
+            )?;
+
+            // The length byte holds one less than the length of the key, which
+            // has no null terminator
+            let len = match data.len().checked_sub(1) {
+                Some(len) => len as u8,
+                None => {
+                    return Err(WriterError::InvalidKey {
+                        key: key.to_owned(),
+                    })
                 },
             };
-        }
+            node_buf
+                .write_u8(len | ARRAY_MASK)
+                .context(NodeUncompressedNameLengthSnafu)?;
+            node_buf
+                .write_all(&data)
+                .context(NodeUncompressedNameDataSnafu)?;
+        },
+    };
 
-        for child in self.children() {
-            child.write_node(options, node_buf, data_buf)?;
+    Ok(())
+}
+
+/// Write the key of a parsed definition. Keys that are already sixbit packed are
+/// copied as-is into compressed output rather than unpacked and packed again.
+fn write_definition_key(
+    options: &Options,
+    node_buf: &mut ByteBufferWrite,
+    definition: &NodeDefinition,
+) -> Result<(), WriterError> {
+    if let (
+        CompressionType::Compressed,
+        NodeData::Some {
+            key: Key::Compressed { size, data },
+            ..
+        },
+    ) = (options.compression, definition.data())
+    {
+        return Sixbit::write_packed(&mut **node_buf, *size, data).context(NodeSixbitNameSnafu);
+    }
+
+    let node_type = definition.node_type;
+    let key = definition
+        .key()
+        .context(DefinitionKeySnafu { node_type })?
+        .ok_or(WriterError::NoNodeKey)?;
+
+    write_key(options, node_buf, &key)
+}
+
+/// Write the node type, key, value and attributes of a `NodeCollection`, everything
+/// but its children and the closing `NodeEnd`.
+pub(crate) fn write_collection_start(
+    collection: &NodeCollection,
+    options: &Options,
+    node_buf: &mut ByteBufferWrite,
+    data_buf: &mut ByteBufferWrite,
+    observer: &mut Option<NodeObserver>,
+) -> Result<(), WriterError> {
+    let (node_type, is_array) = collection.base().node_type_tuple();
+    let array_mask = if is_array { ARRAY_MASK } else { 0 };
+
+    debug!("NodeCollection write_node => name: {:?}, type: {:?}, type_size: {}, type_count: {}, is_array: {}",
+        collection.base().key(),
+        node_type,
+        node_type.size,
+        node_type.count,
+        is_array);
+
+    node_buf
+        .write_u8(node_type as u8 | array_mask)
+        .context(DataWriteSnafu { node_type })?;
+    write_definition_key(options, node_buf, collection.base())?;
+
+    if let Some(observer) = observer {
+        let name = collection
+            .base()
+            .key()
+            .context(DefinitionKeySnafu { node_type })?
+            .ok_or(WriterError::NoNodeKey)?;
+
+        observer(&name, data_buf.position());
+    }
+
+    if node_type != StandardType::NodeStart {
+        let value = collection
+            .base()
+            .value()
+            .context(DefinitionValueSnafu { node_type })?;
+
+        if let (true, Value::Binary(data)) = (options.strict, &value) {
+            if let Some(size) = collection.attr("__size") {
+                let size = size.context(DefinitionValueSnafu {
+                    node_type: StandardType::Attribute,
+                })?;
+                check_binary_size(data.len(), &size)?;
+            }
         }
 
-        // node end always has the array bit set
-        node_buf
-            .write_u8(StandardType::NodeEnd as u8 | ARRAY_MASK)
-            .context(NodeTypeSnafu {
-                node_type: StandardType::NodeEnd,
-            })?;
+        write_value(options, data_buf, node_type, is_array, &value)?;
+    }
 
-        Ok(())
+    for attr in collection.attributes() {
+        let node_type = StandardType::Attribute;
+        let value = attr.value_bytes().ok_or(WriterError::NoNodeValue)?;
+
+        trace!(
+            "NodeCollection write_node => attr: {:?}, value: 0x{:02x?}",
+            attr.key(),
+            value
+        );
+
+        data_buf
+            .buf_write(value)
+            .context(DataBufferSnafu { node_type })?;
+
+        node_buf
+            .write_u8(StandardType::Attribute as u8)
+            .context(DataWriteSnafu { node_type })?;
+        write_definition_key(options, node_buf, attr)?;
     }
+
+    Ok(())
 }
 
 impl Writeable for Node {
@@ -311,117 +482,222 @@ impl Writeable for Node {
         node_buf: &mut ByteBufferWrite,
         data_buf: &mut ByteBufferWrite,
     ) -> Result<(), WriterError> {
-        let (node_type, is_array) = match self.value() {
-            Some(Value::Array(values)) => (values.standard_type(), true),
-            Some(value) => (value.standard_type(), false),
-            None => (StandardType::NodeStart, false),
-        };
-        let array_mask = if is_array { ARRAY_MASK } else { 0 };
-
-        debug!(
-            "Node::write_node => name: {}, type: {:?}, type_size: {}, type_count: {}, is_array: {}",
-            self.key(),
-            node_type,
-            node_type.size,
-            node_type.count,
-            is_array
-        );
+        self.write_node_observed(options, node_buf, data_buf, &mut None)
+    }
 
-        node_buf
-            .write_u8(node_type as u8 | array_mask)
-            .context(DataWriteSnafu { node_type })?;
-        match options.compression {
-            CompressionType::Compressed => {
-                Sixbit::pack(&mut **node_buf, self.key()).context(NodeSixbitNameSnafu)?
-            },
-            CompressionType::Uncompressed => {
-                let data = options.encoding.encode_bytes(self.key()).context(
-                    NodeUncompressedNameEncodeSnafu {
-                        encoding: options.encoding,
-                    },
-                )?;
-                let len = (data.len() - 1) as u8;
-                node_buf
-                    .write_u8(len | ARRAY_MASK)
-                    .context(NodeUncompressedNameLengthSnafu)?;
-                node_buf
-                    .write_all(&data)
-                    .context(NodeUncompressedNameDataSnafu)?;
-            },
-        };
+    fn write_node_observed(
+        &self,
+        options: &Options,
+        node_buf: &mut ByteBufferWrite,
+        data_buf: &mut ByteBufferWrite,
+        observer: &mut Option<NodeObserver>,
+    ) -> Result<(), WriterError> {
+        write_node_start(self, options, node_buf, data_buf, observer)?;
 
-        if let Some(value) = self.value() {
-            write_value(options, data_buf, node_type, is_array, value)?;
+        for child in self.children() {
+            child.write_node_observed(options, node_buf, data_buf, observer)?;
         }
 
-        for (key, value) in self.attributes() {
-            trace!("Node write_node => attr: {}, value: {}", key, value);
-
-            data_buf
-                .write_str(options.encoding, value)
-                .context(DataBufferSnafu { node_type })?;
+        write_node_end(node_buf)
+    }
 
-            node_buf
-                .write_u8(StandardType::Attribute as u8)
-                .context(DataWriteSnafu {
-                    node_type: StandardType::Attribute,
-                })?;
+    fn validate_keys(&self, options: &Options) -> Result<(), WriterError> {
+        let mut stack = vec![self];
 
-            match options.compression {
-                CompressionType::Compressed => {
-                    Sixbit::pack(&mut **node_buf, key).context(NodeSixbitNameSnafu)?
-                },
-                CompressionType::Uncompressed => {
-                    let data = options.encoding.encode_bytes(key).context(
-                        NodeUncompressedNameEncodeSnafu {
-                            encoding: options.encoding,
-                        },
-                    )?;
-                    let len = (data.len() - 1) as u8;
-                    node_buf
-                        .write_u8(len | ARRAY_MASK)
-                        .context(NodeUncompressedNameLengthSnafu)?;
-                    node_buf
-                        .write_all(&data)
-                        .context(NodeUncompressedNameDataSnafu)?;
-                },
-            };
+        while let Some(node) = stack.pop() {
+            check_key(options, node.key())?;
+            for key in node.attribute_keys() {
+                check_key(options, key)?;
+            }
+            stack.extend(node.children());
         }
 
-        for child in self.children() {
-            child.write_node(options, node_buf, data_buf)?;
+        Ok(())
+    }
+}
+
+/// Write the node type, key, value and attributes of a `Node`, everything
+/// but its children and the closing `NodeEnd`.
+pub(crate) fn write_node_start(
+    node: &Node,
+    options: &Options,
+    node_buf: &mut ByteBufferWrite,
+    data_buf: &mut ByteBufferWrite,
+    observer: &mut Option<NodeObserver>,
+) -> Result<(), WriterError> {
+    let (node_type, is_array) = match node.value() {
+        Some(Value::Array(values)) => (values.standard_type(), true),
+        Some(value) => (value.standard_type(), false),
+        None => (StandardType::NodeStart, false),
+    };
+    let array_mask = if is_array { ARRAY_MASK } else { 0 };
+
+    debug!(
+        "Node::write_node => name: {}, type: {:?}, type_size: {}, type_count: {}, is_array: {}",
+        node.key(),
+        node_type,
+        node_type.size,
+        node_type.count,
+        is_array
+    );
+
+    node_buf
+        .write_u8(node_type as u8 | array_mask)
+        .context(DataWriteSnafu { node_type })?;
+    write_key(options, node_buf, node.key())?;
+
+    if let Some(observer) = observer {
+        observer(node.key(), data_buf.position());
+    }
+
+    if let Some(value) = node.value() {
+        if let (true, Value::Binary(data)) = (options.strict, value) {
+            if let Some(size) = node.attributes().get("__size") {
+                check_binary_size(data.len(), size)?;
+            }
         }
 
-        // node end always has the array bit set
+        write_value(options, data_buf, node_type, is_array, value)?;
+    }
+
+    for (key, value) in node.attributes() {
+        trace!("Node write_node => attr: {}, value: {}", key, value);
+
+        data_buf
+            .write_str(options.encoding, value)
+            .context(DataBufferSnafu { node_type })?;
+
         node_buf
-            .write_u8(StandardType::NodeEnd as u8 | ARRAY_MASK)
-            .context(NodeTypeSnafu {
-                node_type: StandardType::NodeEnd,
+            .write_u8(StandardType::Attribute as u8)
+            .context(DataWriteSnafu {
+                node_type: StandardType::Attribute,
             })?;
 
-        Ok(())
+        write_key(options, node_buf, key)?;
     }
+
+    Ok(())
+}
+
+pub(crate) fn write_node_end(node_buf: &mut ByteBufferWrite) -> Result<(), WriterError> {
+    // node end always has the array bit set
+    node_buf
+        .write_u8(StandardType::NodeEnd as u8 | ARRAY_MASK)
+        .context(NodeTypeSnafu {
+            node_type: StandardType::NodeEnd,
+        })?;
+
+    Ok(())
 }
 
+/// Encodes nodes as binary XML.
+///
+/// Output is deterministic: the same input and options always produce the same
+/// bytes. Attributes and children are written in the order they are stored,
+/// which for `Node` and `NodeCollection` is insertion order, and no hashing is
+/// involved.
 pub struct Writer {
     options: Options,
+    observer: Option<NodeObserver>,
 }
 
 impl Writer {
     pub fn new() -> Self {
+        Self::with_options(Options::default())
+    }
+
+    pub fn with_options(options: Options) -> Self {
         Self {
-            options: Options::default(),
+            options,
+            observer: None,
         }
     }
 
-    pub fn with_options(options: Options) -> Self {
-        Self { options }
+    /// Set a callback to be invoked for each node as it is written, such as to
+    /// build an index of data buffer offsets. See `NodeObserver` for the offset
+    /// semantics. With a callback set, `to_binary_parallel` encodes sequentially
+    /// so that the callback sees every node in document order.
+    pub fn set_observer<F>(&mut self, observer: F)
+    where
+        F: FnMut(&str, u64) + Send + 'static,
+    {
+        self.observer = Some(Box::new(observer));
     }
 
     pub fn to_binary<T>(&mut self, input: &T) -> Result<Vec<u8>, WriterError>
     where
         T: Writeable,
     {
+        let header = self.write_header()?;
+        let (node_buf, data_buf) = self.to_buffers(input)?;
+
+        self.frame(header, &node_buf, &data_buf)
+    }
+
+    /// Encode `input` into separate node and data buffers without the header or
+    /// length prefixes, for formats that store them apart.
+    ///
+    /// The node buffer ends with the `FileEnd` marker and is padded to a 4 byte
+    /// boundary, as it is in `to_binary` output. The data buffer is padded to the
+    /// configured alignment.
+    pub fn to_buffers<T>(&mut self, input: &T) -> Result<(Vec<u8>, Vec<u8>), WriterError>
+    where
+        T: Writeable,
+    {
+        if self.options.strict {
+            input.validate_keys(&self.options)?;
+        }
+
+        let mut node_buf = ByteBufferWrite::new(Vec::new());
+        let mut data_buf = self.data_buffer()?;
+
+        input.write_node_observed(
+            &self.options,
+            &mut node_buf,
+            &mut data_buf,
+            &mut self.observer,
+        )?;
+
+        Self::finish_buffers(node_buf, data_buf)
+    }
+
+    /// Encode `input` with its top-level children encoded concurrently on the
+    /// `rayon` thread pool. The output is identical to `to_binary`, which is used
+    /// instead when a callback is set with `set_observer`.
+    #[cfg(feature = "rayon")]
+    pub fn to_binary_parallel<T>(&mut self, input: &T) -> Result<Vec<u8>, WriterError>
+    where
+        T: ParallelWriteable + Sync,
+    {
+        if self.observer.is_some() {
+            return self.to_binary(input);
+        }
+
+        let header = self.write_header()?;
+
+        if self.options.strict {
+            input.validate_keys(&self.options)?;
+        }
+
+        let mut node_buf = ByteBufferWrite::new(Vec::new());
+        let mut data_buf = self.data_buffer()?;
+
+        crate::parallel::write_node_parallel(input, &self.options, &mut node_buf, &mut data_buf)?;
+
+        let (node_buf, data_buf) = Self::finish_buffers(node_buf, data_buf)?;
+        self.frame(header, &node_buf, &data_buf)
+    }
+
+    fn data_buffer(&self) -> Result<ByteBufferWrite, WriterError> {
+        let alignment = self.options.alignment;
+        if !alignment.is_power_of_two() {
+            return Err(WriterError::InvalidAlignment { alignment });
+        }
+
+        Ok(ByteBufferWrite::with_alignment(Vec::new(), alignment))
+    }
+
+    fn write_header(&self) -> Result<Vec<u8>, WriterError> {
         let mut header = Cursor::new(Vec::with_capacity(8));
         header.write_u8(SIGNATURE).context(SignatureSnafu)?;
 
@@ -434,11 +710,13 @@ impl Writer {
             .write_u8(0xFF ^ encoding)
             .context(EncodingNegateSnafu)?;
 
-        let mut node_buf = ByteBufferWrite::new(Vec::new());
-        let mut data_buf = ByteBufferWrite::new(Vec::new());
-
-        input.write_node(&self.options, &mut node_buf, &mut data_buf)?;
+        Ok(header.into_inner())
+    }
 
+    fn finish_buffers(
+        mut node_buf: ByteBufferWrite,
+        data_buf: ByteBufferWrite,
+    ) -> Result<(Vec<u8>, Vec<u8>), WriterError> {
         node_buf
             .write_u8(StandardType::FileEnd as u8 | ARRAY_MASK)
             .context(NodeTypeSnafu {
@@ -448,27 +726,46 @@ impl Writer {
             node_type: StandardType::FileEnd,
         })?;
 
-        let mut output = header.into_inner();
-
-        let node_buf = node_buf.into_inner();
-        debug!(
-            "to_binary_internal => node_buf len: {0} (0x{0:x})",
-            node_buf.len()
-        );
-        output
-            .write_u32::<BigEndian>(node_buf.len() as u32)
-            .context(NodeBufferLengthSnafu)?;
-        output.extend_from_slice(&node_buf);
+        Ok((node_buf.into_inner(), data_buf.into_inner()))
+    }
 
-        let data_buf = data_buf.into_inner();
+    fn frame(
+        &self,
+        mut output: Vec<u8>,
+        node_buf: &[u8],
+        data_buf: &[u8],
+    ) -> Result<Vec<u8>, WriterError> {
         debug!(
-            "to_binary_internal => data_buf len: {0} (0x{0:x})",
+            "to_binary_internal => node_buf len: {0} (0x{0:x}), data_buf len: {1} (0x{1:x})",
+            node_buf.len(),
             data_buf.len()
         );
-        output
-            .write_u32::<BigEndian>(data_buf.len() as u32)
-            .context(DataBufferLengthSnafu)?;
-        output.extend_from_slice(&data_buf);
+
+        let write_node_buf = |output: &mut Vec<u8>| -> Result<(), WriterError> {
+            output
+                .write_u32::<BigEndian>(node_buf.len() as u32)
+                .context(NodeBufferLengthSnafu)?;
+            output.extend_from_slice(node_buf);
+            Ok(())
+        };
+        let write_data_buf = |output: &mut Vec<u8>| -> Result<(), WriterError> {
+            output
+                .write_u32::<BigEndian>(data_buf.len() as u32)
+                .context(DataBufferLengthSnafu)?;
+            output.extend_from_slice(data_buf);
+            Ok(())
+        };
+
+        match self.options.buffer_layout {
+            BufferLayout::Standard => {
+                write_node_buf(&mut output)?;
+                write_data_buf(&mut output)?;
+            },
+            BufferLayout::DataFirst => {
+                write_data_buf(&mut output)?;
+                write_node_buf(&mut output)?;
+            },
+        }
 
         Ok(output)
     }
@@ -480,3 +777,273 @@ impl Default for Writer {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use crate::value::ValueArray;
+
+    #[test]
+    fn uncompressed_key_length() {
+        let options = Options::utf8_uncompressed();
+        let output = Writer::with_options(options.clone())
+            .to_binary(&Node::new("ab"))
+            .unwrap();
+        assert_eq!(&output[8..12], &[
+            StandardType::NodeStart as u8,
+            (2 - 1) | ARRAY_MASK,
+            b'a',
+            b'b'
+        ]);
+
+        let (collection, _) = crate::from_slice(&output).unwrap();
+        assert_eq!(collection.base().key().unwrap().as_deref(), Some("ab"));
+
+        assert!(matches!(
+            Writer::with_options(options).to_binary(&Node::new("")),
+            Err(WriterError::InvalidKey { .. })
+        ));
+    }
+
+    #[test]
+    fn packed_key_pass_through() {
+        let node = Node::with("root", &[("id", "1"), ("_attr", "x")], vec![
+            Node::with_value("Key_9", Value::U32(9)),
+            Node::with_nodes("nested", vec![Node::with_value(
+                "s",
+                Value::String("abc".into()),
+            )]),
+        ]);
+        let input = node.to_binary().unwrap();
+
+        let (collection, _) = crate::from_slice(&input).unwrap();
+        assert!(matches!(collection.base().data(), NodeData::Some {
+            key: Key::Compressed { .. },
+            ..
+        }));
+        assert_eq!(collection.to_binary().unwrap(), input);
+
+        let options = Options::builder()
+            .compression(CompressionType::Uncompressed)
+            .build();
+        let uncompressed = collection.to_binary_with_options(options).unwrap();
+        let (collection, _) = crate::from_slice(&uncompressed).unwrap();
+        assert_eq!(collection.as_node().unwrap(), node);
+    }
+
+    #[test]
+    fn to_buffers() {
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("a", Value::U8(1)),
+            Node::with_value("b", Value::String("text".into())),
+        ]);
+
+        let (node_buf, data_buf) = Writer::new().to_buffers(&node).unwrap();
+        assert_eq!(node_buf.len() % 4, 0);
+        assert_eq!(data_buf.len() % 4, 0);
+        assert!(node_buf.contains(&(StandardType::FileEnd as u8 | ARRAY_MASK)));
+
+        let output = node.to_binary().unwrap();
+        let node_end = 8 + node_buf.len();
+        assert_eq!(&output[8..node_end], &node_buf[..]);
+        assert_eq!(&output[node_end + 4..], &data_buf[..]);
+    }
+
+    #[test]
+    fn observer_offsets() {
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("a", Value::S32(1)),
+            Node::with_value("b", Value::String("hi".into())),
+            Node::with_value("c", Value::S64(2)),
+        ]);
+
+        let index = Arc::new(Mutex::new(Vec::new()));
+        let mut writer = Writer::new();
+        writer.set_observer({
+            let index = Arc::clone(&index);
+            move |key, offset| index.lock().unwrap().push((key.to_owned(), offset))
+        });
+        let output = writer.to_binary(&node).unwrap();
+        assert_eq!(output, node.to_binary().unwrap());
+
+        assert_eq!(*index.lock().unwrap(), [
+            ("root".to_owned(), 0),
+            ("a".to_owned(), 0),
+            ("b".to_owned(), 4),
+            ("c".to_owned(), 12),
+        ]);
+    }
+
+    #[test]
+    fn array_flag_mismatch() {
+        let options = Options::default();
+        let mut data_buf = ByteBufferWrite::new(Vec::new());
+
+        let array = Value::Array(ValueArray::U8(vec![1, 2]));
+        assert!(matches!(
+            write_value(&options, &mut data_buf, StandardType::U8, false, &array),
+            Err(WriterError::ArrayFlagMismatch {
+                node_type: StandardType::U8,
+                is_array: false,
+            })
+        ));
+
+        let value = Value::U8(1);
+        assert!(matches!(
+            write_value(&options, &mut data_buf, StandardType::U8, true, &value),
+            Err(WriterError::ArrayFlagMismatch {
+                node_type: StandardType::U8,
+                is_array: true,
+            })
+        ));
+    }
+
+    #[test]
+    fn packed_values_cross_representation() {
+        use crate::value::ValueArray;
+
+        // 1 and 2 byte values share 4 byte slots in the data buffer, while arrays
+        // and larger values are aligned on their own
+        let node = Node::with_nodes("root", vec![
+            Node::with_value("a", Value::U8(1)),
+            Node::with_value("b", Value::S16(-2)),
+            Node::with_value("c", Value::Array(ValueArray::U8(vec![3, 4, 5]))),
+            Node::with_value("d", Value::Boolean(true)),
+            Node::with_value("e", Value::U8_2([6, 7])),
+            Node::with_value("f", Value::U32(8)),
+            Node::with_value("g", Value::S8(-9)),
+            Node::with_value("h", Value::Array(ValueArray::U16(vec![10]))),
+            Node::with_value("i", Value::U16(11)),
+            Node::with_value("j", Value::U8(12)),
+        ]);
+
+        let binary = Writer::new().to_binary(&node).unwrap();
+        let (collection, _) = crate::from_slice(&binary).unwrap();
+        assert_eq!(collection.as_node().unwrap(), node);
+
+        assert_eq!(Writer::new().to_binary(&collection).unwrap(), binary);
+        assert_eq!(
+            Writer::new()
+                .to_binary(&collection.as_node().unwrap())
+                .unwrap(),
+            binary
+        );
+
+        let text = crate::to_text_xml(&collection).unwrap();
+        let (collection, _) = crate::from_text_xml(&text).unwrap();
+        assert_eq!(Writer::new().to_binary(&collection).unwrap(), binary);
+    }
+
+    #[test]
+    fn strict_invalid_keys() {
+        let mut node = Node::new("root");
+        node.append_child(Node::with_attrs("child", &[("bad-key", "1")]));
+
+        // Without strict mode, the error is only found partway through writing
+        assert!(matches!(
+            Writer::new().to_binary(&node),
+            Err(WriterError::NodeSixbitName { .. })
+        ));
+
+        let options = Options::builder().strict(true).build();
+        let mut writer = Writer::with_options(options);
+        assert!(matches!(
+            writer.to_binary(&node),
+            Err(WriterError::InvalidKey { key }) if key == "bad-key"
+        ));
+
+        let empty = Node::with_nodes("root", vec![Node::new("")]);
+        assert!(matches!(
+            writer.to_binary(&empty),
+            Err(WriterError::InvalidKey { key }) if key.is_empty()
+        ));
+
+        // Uncompressed keys are not limited to sixbit characters
+        let options = Options::builder()
+            .strict(true)
+            .compression(CompressionType::Uncompressed)
+            .build();
+        let binary = Writer::with_options(options.clone())
+            .to_binary(&node)
+            .unwrap();
+        let (collection, _) = crate::from_slice(&binary).unwrap();
+        assert!(Writer::with_options(options).to_binary(&collection).is_ok());
+    }
+
+    #[test]
+    fn strict_binary_size() {
+        let mut node = Node::with_value("bin", Value::Binary(vec![1, 2, 3]));
+        node.set_attr("__size", "4");
+
+        assert!(Writer::new().to_binary(&node).is_ok());
+
+        let options = Options::builder().strict(true).build();
+        let mut writer = Writer::with_options(options);
+        assert!(matches!(
+            writer.to_binary(&node),
+            Err(WriterError::MismatchedBinaryNodeLength { len: 3, .. })
+        ));
+
+        let collection = crate::from_slice(&Writer::new().to_binary(&node).unwrap())
+            .unwrap()
+            .0;
+        assert!(matches!(
+            writer.to_binary(&collection),
+            Err(WriterError::MismatchedBinaryNodeLength { len: 3, .. })
+        ));
+
+        node.set_attr("__size", "3");
+        assert!(writer.to_binary(&node).is_ok());
+    }
+
+    #[test]
+    fn custom_alignment() {
+        use bytes::Bytes;
+
+        use crate::reader::Reader;
+
+        let mut node = Node::with_value("root", Value::U8(1));
+        node.append_child(Node::with_value("a", Value::String("abc".into())));
+        node.append_child(Node::with_value("b", Value::S16(2)));
+        node.append_child(Node::with_value("c", Value::U32_3([1, 2, 3])));
+
+        let options = Options::builder().alignment(16).build();
+        let output = Writer::with_options(options.clone())
+            .to_binary(&node)
+            .unwrap();
+        assert_ne!(output, Writer::new().to_binary(&node).unwrap());
+
+        let reader = Reader::with_options(Bytes::from(output), &options).unwrap();
+        assert_eq!(reader.data_buffer_len() % 16, 0);
+
+        let collection: NodeCollection = reader.collect::<Option<_>>().unwrap();
+        assert_eq!(collection.as_node().unwrap(), node);
+
+        let options = Options::builder().alignment(3).build();
+        assert!(matches!(
+            Writer::with_options(options).to_binary(&node),
+            Err(WriterError::InvalidAlignment { alignment: 3 })
+        ));
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let node = Node::with("root", &[("z", "1"), ("a", "2"), ("m", "3")], vec![
+            Node::with_attrs_value("b", &[("y", "1"), ("x", "2")], Value::U8(1)),
+            Node::with_value("a", Value::String("text".into())),
+            Node::with_value("b", Value::Float(0.5)),
+        ]);
+
+        let first = node.to_binary().unwrap();
+        for _ in 0..8 {
+            assert_eq!(Writer::new().to_binary(&node.clone()).unwrap(), first);
+        }
+
+        let (collection, _) = crate::from_slice(&first).unwrap();
+        assert_eq!(collection.to_binary().unwrap(), first);
+        assert_eq!(collection.as_node().unwrap().to_binary().unwrap(), first);
+    }
+}
@@ -64,6 +64,12 @@ fn main() -> Result<(), anyhow::Error> {
                 .short('p')
                 .long("printer"),
         )
+        .arg(
+            Arg::new("max-depth")
+                .help("Only print the top levels of the document with the printer")
+                .long("max-depth")
+                .takes_value(true),
+        )
         .arg(
             Arg::new("encoding")
                 .help("Set the encoding used when encoding kbin data")
@@ -80,6 +86,10 @@ fn main() -> Result<(), anyhow::Error> {
         .get_matches();
 
     let printer_enabled = matches.is_present("printer");
+    let mut printer = Printer::new();
+    if let Some(max_depth) = matches.value_of("max-depth") {
+        printer.max_depth(max_depth.parse().context("Invalid maximum depth")?);
+    }
     let file_name = matches.value_of("input").unwrap();
     let output_encoding = if let Some(label) = matches.value_of("encoding") {
         let encoding =
@@ -104,7 +114,7 @@ fn main() -> Result<(), anyhow::Error> {
 
     if kbinxml::is_binary_xml(&contents) {
         if printer_enabled {
-            Printer::run(contents.clone())?;
+            printer.dump(contents.clone())?;
         }
 
         let (collection, _encoding) = kbinxml::from_slice(&contents)?;
@@ -121,7 +131,7 @@ fn main() -> Result<(), anyhow::Error> {
         let buf = kbinxml::to_binary_with_options(options, &collection)?;
 
         if printer_enabled {
-            Printer::run(buf.clone())?;
+            printer.dump(buf.clone())?;
         }
 
         io::stdout().write_all(&buf)?;
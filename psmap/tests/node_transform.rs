@@ -0,0 +1,43 @@
+use std::error::Error;
+
+use kbinxml::Node;
+use psmap::psmap;
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    price: u32,
+}
+
+fn entry(input: &[u8]) -> Result<Entry, Box<dyn Error>> {
+    let (nodes, _encoding) = kbinxml::from_text_xml(input)?;
+    let node = nodes.as_node()?;
+
+    Ok(psmap! {
+        output: Entry,
+        inputs: [
+            node: {
+                "entry" => {
+                    value => price,
+                    node_transform => |node: &Node| -> Result<u32, Box<dyn Error>> {
+                        let price: u32 = node.value().ok_or("no price")?.try_into()?;
+                        let scale = node.attributes().get("scale").map_or(Ok(1), |s| s.parse())?;
+
+                        Ok(price * scale)
+                    },
+                },
+            },
+        ],
+    })
+}
+
+#[test]
+fn node_transform_reads_attribute() {
+    let input = br#"<test><entry __type="u32" scale="3">20</entry></test>"#;
+    assert_eq!(entry(input).unwrap(), Entry { price: 60 });
+
+    let input = br#"<test><entry __type="u32">20</entry></test>"#;
+    assert_eq!(entry(input).unwrap(), Entry { price: 20 });
+
+    let input = br#"<test><entry __type="u32" scale="x">20</entry></test>"#;
+    assert!(entry(input).is_err());
+}
@@ -0,0 +1,23 @@
+use psmap::psmap;
+
+struct Entry {
+    id: u8,
+    name: String,
+}
+
+fn entry(node: &kbinxml::Node) -> Result<Entry, Box<dyn std::error::Error>> {
+    let id = 5;
+
+    Ok(psmap! {
+        output: Entry,
+        include: [id],
+        inputs: [
+            node: {
+                "id" => id,
+                "name" => name,
+            },
+        ],
+    })
+}
+
+fn main() {}
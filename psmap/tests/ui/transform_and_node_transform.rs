@@ -0,0 +1,22 @@
+use psmap::psmap;
+
+struct Entry {
+    price: u32,
+}
+
+fn entry(node: &kbinxml::Node) -> Result<Entry, Box<dyn std::error::Error>> {
+    Ok(psmap! {
+        output: Entry,
+        inputs: [
+            node: {
+                "entry" => {
+                    value => price,
+                    transform => |price: u32| price * 2,
+                    node_transform => |node: &kbinxml::Node| Ok(0),
+                },
+            },
+        ],
+    })
+}
+
+fn main() {}
@@ -19,6 +19,7 @@ mod kw {
     custom_keyword!(default);
     custom_keyword!(include);
     custom_keyword!(inputs);
+    custom_keyword!(node_transform);
     custom_keyword!(output);
     custom_keyword!(optional);
     custom_keyword!(transform);
@@ -49,6 +50,7 @@ struct Mapping {
     subnodes: Option<Punctuated<Mapping, Token![,]>>,
     value: Option<Ident>,
     transform: Option<Expr>,
+    node_transform: Option<Expr>,
     default_value: Option<Expr>,
     optional_value: bool,
 }
@@ -183,6 +185,25 @@ impl Mapping {
             None
         };
 
+        let node_transform = if let Some(keyword) = content.parse::<Option<kw::node_transform>>()? {
+            //eprintln!("Mapping: node_transform");
+            content.parse::<Token![=>]>()?;
+
+            if transform.is_some() {
+                return Err(syn::Error::new(
+                    keyword.span(),
+                    "`transform` and `node_transform` cannot be used together",
+                ));
+            }
+
+            let value = content.parse()?;
+            content.parse::<Token![,]>()?;
+
+            Some(value)
+        } else {
+            None
+        };
+
         let default_value = if content.parse::<Option<kw::default>>()?.is_some() {
             //eprintln!("Mapping: default");
             content.parse::<Token![=>]>()?;
@@ -212,6 +233,7 @@ impl Mapping {
             subnodes: Some(subnodes),
             value,
             transform,
+            node_transform,
             default_value,
             optional_value,
         })
@@ -233,6 +255,7 @@ impl Parse for Mapping {
                 subnodes: None,
                 value: input.parse()?,
                 transform: None,
+                node_transform: None,
                 default_value: None,
                 optional_value: false,
             })
@@ -319,6 +342,7 @@ impl PsmapOutput {
         let Mapping {
             source,
             transform,
+            node_transform,
             default_value,
             optional_value,
             ..
@@ -348,13 +372,22 @@ impl PsmapOutput {
         // `Node::value` returns `Option<&Value>` and `TryInto::try_into` should only be called
         // if there is `Some(value)`, but this returns `Option<Result<T, E>>`. `Option::transpose`
         // converts that to `Result<Option<T>, E>` which `?` can be used on.
-        let body_tokens = quote_spanned! {source.span()=>
-          let child_value = child.value()
-            .map(|v| v.try_into())
-            .transpose()?
-            #map_value;
-          #transform
-          #target = Some(child_value);
+        //
+        // `node_transform` skips all of that and hands the whole `&Node` to the closure so it
+        // can read attributes and children as well.
+        let body_tokens = match node_transform {
+            Some(node_transform) => quote_spanned! {node_transform.span()=>
+              let child_value = #node_transform(child)?;
+              #target = Some(child_value);
+            },
+            None => quote_spanned! {source.span()=>
+              let child_value = child.value()
+                .map(|v| v.try_into())
+                .transpose()?
+                #map_value;
+              #transform
+              #target = Some(child_value);
+            },
         };
         let field_tokens = match default_value {
             Some(default_value) => quote_spanned! {target.span()=>
@@ -480,6 +513,76 @@ impl PsmapOutput {
     }
 }
 
+impl Mapping {
+    /// Collect the output fields this mapping and its subnodes assign to, along
+    /// with the source name the field is mapped from.
+    fn collect_targets<'a>(&'a self, targets: &mut Vec<(&'a Ident, &'a LitStr)>) {
+        if let Some(value) = &self.value {
+            targets.push((value, &self.source));
+        }
+
+        if let Some(attributes) = &self.attributes {
+            for attr in attributes.iter() {
+                targets.push((&attr.target, &attr.source));
+            }
+        }
+
+        if let Some(subnodes) = &self.subnodes {
+            for subnode in subnodes.iter() {
+                subnode.collect_targets(targets);
+            }
+        }
+    }
+}
+
+/// Check that no field copied in with `include` is also assigned by a mapping,
+/// which would otherwise surface as a duplicate field error in the generated
+/// struct expression.
+fn check_includes(
+    includes: &Option<Includes>,
+    blocks: &Punctuated<InputBlock, Token![,]>,
+) -> Result<()> {
+    let includes = match includes {
+        Some(includes) => includes,
+        None => return Ok(()),
+    };
+
+    let mut targets = Vec::new();
+    for block in blocks.iter() {
+        for mapping in block.mappings.iter() {
+            mapping.collect_targets(&mut targets);
+        }
+    }
+
+    let mut errors: Option<syn::Error> = None;
+    for include in includes.includes.iter() {
+        for (target, source) in targets.iter().filter(|(target, _)| *target == include) {
+            let mut error = syn::Error::new(
+                include.span(),
+                format!("included field `{}` collides with a mapped field", include),
+            );
+            error.combine(syn::Error::new(
+                target.span(),
+                format!(
+                    "field `{}` is mapped from {:?} here",
+                    target,
+                    source.value()
+                ),
+            ));
+
+            match &mut errors {
+                Some(errors) => errors.combine(error),
+                None => errors = Some(error),
+            }
+        }
+    }
+
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(()),
+    }
+}
+
 #[proc_macro]
 pub fn psmap(input: TokenStream) -> TokenStream {
     let Psmap {
@@ -488,6 +591,12 @@ pub fn psmap(input: TokenStream) -> TokenStream {
         inputs: Inputs { blocks },
     } = parse_macro_input!(input as Psmap);
 
+    if let Err(e) = check_includes(&includes, &blocks) {
+        let errors = e.to_compile_error();
+
+        return quote!({ #errors }).into();
+    }
+
     let mut output = PsmapOutput {
         struct_name: struct_name.clone(),
         definitions: TokenStream2::new(),